@@ -0,0 +1,389 @@
+//! The task list and the subtask links between its tasks, independent of any front-end
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Result, TaskmanError};
+use crate::task::{Task, TaskStatus};
+
+/// All tasks by id
+///
+/// Reading goes through the underlying map, changes through the methods keeping the subtask
+/// links consistent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskCollection {
+    tasks: HashMap<Uuid, Task>,
+    /// While tracking, the tasks changed so far as they were before
+    touched: Option<HashMap<Uuid, Option<Task>>>,
+}
+
+impl TaskCollection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` and returns the tasks it may have changed as they were before
+    pub fn track<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> (R, HashMap<Uuid, Option<Task>>) {
+        let outer = self.touched.replace(HashMap::new());
+        let result = f(self);
+        let touched = std::mem::replace(&mut self.touched, outer).unwrap_or_default();
+        if let Some(outer) = &mut self.touched {
+            for (id, before) in &touched {
+                outer.entry(*id).or_insert_with(|| before.clone());
+            }
+        }
+        (result, touched)
+    }
+
+    /// Keeps the task `id` as it is before it is changed
+    fn touch(&mut self, id: Uuid) {
+        if let Some(touched) = &mut self.touched {
+            touched
+                .entry(id)
+                .or_insert_with(|| self.tasks.get(&id).cloned());
+        }
+    }
+
+    /// Adds a task or replaces the one with the same id
+    pub fn insert(&mut self, task: Task) -> Option<Task> {
+        self.touch(task.get_uuid());
+        self.tasks.insert(task.get_uuid(), task)
+    }
+
+    /// Removes a task as is, links to it from other tasks are kept
+    pub fn remove(&mut self, id: Uuid) -> Option<Task> {
+        self.touch(id);
+        self.tasks.remove(&id)
+    }
+
+    /// Removes a task and unlinks it from the tasks it is a subtask of
+    pub fn delete(&mut self, id: Uuid) -> Option<Task> {
+        let parents: Vec<Uuid> = self.parents(id).map(Task::get_uuid).collect();
+        for parent in parents {
+            if let Some(task) = self.get_mut(parent) {
+                task.remove_subtask(id).ok();
+            }
+        }
+        self.remove(id)
+    }
+
+    pub fn clear(&mut self) {
+        let ids: Vec<Uuid> = self.tasks.keys().copied().collect();
+        for id in ids {
+            self.touch(id);
+        }
+        self.tasks.clear();
+    }
+
+    /// Replaces all tasks, only those that differ are changed
+    pub fn replace(&mut self, tasks: impl IntoIterator<Item = Task>) {
+        let mut gone: HashSet<Uuid> = self.tasks.keys().copied().collect();
+        for task in tasks {
+            gone.remove(&task.get_uuid());
+            if self.tasks.get(&task.get_uuid()) != Some(&task) {
+                self.insert(task);
+            }
+        }
+        for id in gone {
+            self.remove(id);
+        }
+    }
+
+    pub fn get_mut(&mut self, id: Uuid) -> Option<&mut Task> {
+        self.touch(id);
+        self.tasks.get_mut(&id)
+    }
+
+    /// Tasks from oldest to newest
+    pub fn sorted(&self) -> Vec<&Task> {
+        let mut tasks: Vec<&Task> = self.tasks.values().collect();
+        tasks.sort_by_key(|task| (task.get_creation_time(), task.get_uuid()));
+        tasks
+    }
+
+    pub fn into_sorted(self) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self.tasks.into_values().collect();
+        tasks.sort_by_key(|task| (task.get_creation_time(), task.get_uuid()));
+        tasks
+    }
+
+    /// The tasks `id` is a subtask of
+    pub fn parents(&self, id: Uuid) -> impl Iterator<Item = &Task> {
+        self.tasks.values().filter(move |task| task.has_subtask(id))
+    }
+
+    /// Existing subtasks of `id`, links to missing tasks are skipped
+    pub fn subtasks(&self, id: Uuid) -> impl Iterator<Item = &Task> {
+        self.tasks
+            .get(&id)
+            .and_then(|task| task.get_subtasks())
+            .into_iter()
+            .flatten()
+            .filter_map(|(sub, _)| self.tasks.get(sub))
+    }
+
+    /// All tasks below `id`, each once even if it is linked several times
+    pub fn descendants(&self, id: Uuid) -> HashSet<Uuid> {
+        let mut found = HashSet::new();
+        let mut pending = vec![id];
+        while let Some(next) = pending.pop() {
+            for sub in self.subtasks(next) {
+                if found.insert(sub.get_uuid()) {
+                    pending.push(sub.get_uuid());
+                }
+            }
+        }
+        found
+    }
+
+    /// Removes the links of the tasks `ids` to missing tasks and those closing a cycle, which
+    /// [`link`](Self::link) would have refused, returning them as parent and child
+    pub fn drop_broken_links(&mut self, ids: impl IntoIterator<Item = Uuid>) -> Vec<(Uuid, Uuid)> {
+        let mut dropped = Vec::new();
+        for id in ids {
+            let subs: Vec<Uuid> = self
+                .tasks
+                .get(&id)
+                .and_then(|task| task.get_subtasks())
+                .into_iter()
+                .flatten()
+                .map(|(sub, _)| *sub)
+                .collect();
+            for sub in subs {
+                let broken = sub == id
+                    || !self.tasks.contains_key(&sub)
+                    || self.descendants(sub).contains(&id);
+                if broken && self.unlink(id, sub).is_ok() {
+                    dropped.push((id, sub));
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Whether `child` can become a subtask of `parent` without creating a cycle
+    pub fn can_link(&self, parent: Uuid, child: Uuid) -> bool {
+        self.check_link(parent, child).is_ok()
+    }
+
+    fn check_link(&self, parent: Uuid, child: Uuid) -> Result<()> {
+        let task = self
+            .tasks
+            .get(&parent)
+            .ok_or(TaskmanError::TaskNotFound(parent))?;
+        if !self.tasks.contains_key(&child) {
+            return Err(TaskmanError::TaskNotFound(child));
+        }
+        if task.has_subtask(child) {
+            return Err(TaskmanError::SubtaskExists(child));
+        }
+        if parent == child || self.descendants(child).contains(&parent) {
+            return Err(TaskmanError::Cycle { parent, child });
+        }
+        Ok(())
+    }
+
+    /// Makes `child` a subtask of `parent`
+    pub fn link(&mut self, parent: Uuid, child: Uuid) -> Result<()> {
+        self.check_link(parent, child)?;
+        let name = self.tasks[&child].name.clone();
+        self.task_mut(parent)?.add_subtask(child, name)
+    }
+
+    pub fn unlink(&mut self, parent: Uuid, child: Uuid) -> Result<()> {
+        self.task_mut(parent)?.remove_subtask(child)
+    }
+
+    fn task_mut(&mut self, id: Uuid) -> Result<&mut Task> {
+        self.get_mut(id).ok_or(TaskmanError::TaskNotFound(id))
+    }
+
+    /// Renames a task along with the name its parents keep for it
+    pub fn rename(&mut self, id: Uuid, name: &str) {
+        if let Some(task) = self.get_mut(id) {
+            task.name = name.to_string();
+        }
+        self.sync_name(id);
+    }
+
+    /// Updates the name the parents of `id` keep for it after it was renamed
+    pub fn sync_name(&mut self, id: Uuid) {
+        let Some(name) = self.tasks.get(&id).map(|task| task.name.clone()) else {
+            return;
+        };
+        let stale: Vec<Uuid> = self
+            .parents(id)
+            .filter(|task| {
+                task.get_subtasks()
+                    .into_iter()
+                    .flatten()
+                    .any(|(sub, sub_name)| *sub == id && *sub_name != name)
+            })
+            .map(Task::get_uuid)
+            .collect();
+        for parent in stale {
+            let Some(task) = self.get_mut(parent) else {
+                continue;
+            };
+            for (sub, sub_name) in task.subtasks.iter_mut().flatten() {
+                if *sub == id {
+                    *sub_name = name.clone();
+                }
+            }
+        }
+    }
+
+    pub fn start(&mut self, id: Uuid) -> Result<()> {
+        self.start_with(id, &SystemClock)
+    }
+
+    pub fn start_with(&mut self, id: Uuid, clock: &dyn Clock) -> Result<()> {
+        self.task_mut(id)?.start_with(clock);
+        Ok(())
+    }
+
+    pub fn finish(&mut self, id: Uuid) -> Result<()> {
+        self.finish_with(id, &SystemClock)
+    }
+
+    pub fn finish_with(&mut self, id: Uuid, clock: &dyn Clock) -> Result<()> {
+        self.task_mut(id)?.finish_with(clock);
+        Ok(())
+    }
+
+    pub fn pause(&mut self, id: Uuid) -> Result<()> {
+        self.pause_with(id, &SystemClock)
+    }
+
+    pub fn pause_with(&mut self, id: Uuid, clock: &dyn Clock) -> Result<()> {
+        self.task_mut(id)?.pause_with(clock);
+        Ok(())
+    }
+
+    pub fn resume(&mut self, id: Uuid) -> Result<()> {
+        self.resume_with(id, &SystemClock)
+    }
+
+    pub fn resume_with(&mut self, id: Uuid, clock: &dyn Clock) -> Result<()> {
+        self.task_mut(id)?.resume_with(clock);
+        Ok(())
+    }
+
+    pub fn add_pomodoro(&mut self, id: Uuid) -> Result<()> {
+        self.task_mut(id)?.add_pomodoro();
+        Ok(())
+    }
+
+    pub fn status(&self, id: Uuid) -> Option<TaskStatus> {
+        self.tasks.get(&id).map(|task| task.status())
+    }
+
+    /// Time tracked on a task and all tasks below it
+    pub fn total_time(&self, id: Uuid) -> Duration {
+        self.descendants(id)
+            .into_iter()
+            .chain([id])
+            .filter_map(|id| self.tasks.get(&id))
+            .fold(Duration::zero(), |acc, task| acc + task.tracked_time())
+    }
+}
+
+impl Deref for TaskCollection {
+    type Target = HashMap<Uuid, Task>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tasks
+    }
+}
+
+impl From<HashMap<Uuid, Task>> for TaskCollection {
+    fn from(tasks: HashMap<Uuid, Task>) -> Self {
+        Self {
+            tasks,
+            ..Default::default()
+        }
+    }
+}
+
+impl IntoIterator for TaskCollection {
+    type Item = Task;
+    type IntoIter = std::collections::hash_map::IntoValues<Uuid, Task>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tasks.into_values()
+    }
+}
+
+impl FromIterator<Task> for TaskCollection {
+    fn from_iter<I: IntoIterator<Item = Task>>(iter: I) -> Self {
+        Self {
+            tasks: iter
+                .into_iter()
+                .map(|task| (task.get_uuid(), task))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn named(name: &str) -> Task {
+        Task {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn link_without_cycles() {
+        let created = [named("A"), named("B"), named("C")];
+        let [a, b, c] = created.clone().map(|task| task.get_uuid());
+        let mut tasks: TaskCollection = created.into_iter().collect();
+
+        tasks.link(a, b).unwrap();
+        tasks.link(b, c).unwrap();
+        assert!(matches!(
+            tasks.link(a, b),
+            Err(TaskmanError::SubtaskExists(_))
+        ));
+        assert!(matches!(tasks.link(c, a), Err(TaskmanError::Cycle { .. })));
+        assert!(matches!(tasks.link(a, a), Err(TaskmanError::Cycle { .. })));
+        assert_eq!(tasks.descendants(a), HashSet::from([b, c]));
+        assert_eq!(tasks.parents(c).count(), 1);
+
+        tasks.rename(c, "Renamed");
+        assert_eq!(tasks[&b].get_subtasks().unwrap()[0].1, "Renamed");
+
+        tasks.delete(b);
+        assert!(tasks[&a].get_subtasks().unwrap().is_empty());
+        assert!(matches!(
+            tasks.unlink(a, b),
+            Err(TaskmanError::SubtaskMissing(_))
+        ));
+        assert!(matches!(tasks.start(b), Err(TaskmanError::TaskNotFound(_))));
+    }
+
+    #[test]
+    fn total_time() {
+        let mut tasks = TaskCollection::new();
+        let mut parent = named("Parent");
+        let mut child = named("Child");
+        let start = parent.get_creation_time();
+        parent.set_times(Some(start), Some(start + Duration::minutes(10)));
+        child.set_times(Some(start), Some(start + Duration::minutes(5)));
+        let (parent_id, child_id) = (parent.get_uuid(), child.get_uuid());
+        tasks.insert(parent);
+        tasks.insert(child);
+        tasks.link(parent_id, child_id).unwrap();
+
+        assert_eq!(tasks.total_time(parent_id), Duration::minutes(15));
+        assert_eq!(tasks.total_time(child_id), Duration::minutes(5));
+    }
+}
@@ -0,0 +1,244 @@
+//! Undoable changes to the task list
+//!
+//! Every mutation is recorded as a [`Command`] holding the affected tasks before and after the
+//! change, so undoing or redoing it only has to put the right copies back.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::collection::TaskCollection;
+use crate::task::Task;
+
+/// Number of commands kept for undoing
+pub const HISTORY_LIMIT: usize = 100;
+
+/// A single task before and after a command, `None` if it did not or does not exist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+impl Change {
+    pub fn id(&self) -> Uuid {
+        self.before
+            .as_ref()
+            .or(self.after.as_ref())
+            .map(|task| task.get_uuid())
+            .expect("a change has a task before or after it")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Command {
+    pub label: String,
+    pub changes: Vec<Change>,
+}
+
+impl Command {
+    /// Runs `f` on the task list and returns what it changed
+    pub fn execute<R>(
+        label: &str,
+        tasks: &mut TaskCollection,
+        f: impl FnOnce(&mut TaskCollection) -> R,
+    ) -> (R, Option<Command>) {
+        // Only the tasks `f` touched are compared, not the whole list
+        let (result, touched) = tasks.track(f);
+        let mut changes: Vec<Change> = touched
+            .into_iter()
+            .filter_map(|(id, before)| {
+                let after = tasks.get(&id);
+                (before.as_ref() != after).then(|| Change {
+                    before,
+                    after: after.cloned(),
+                })
+            })
+            .collect();
+        if changes.is_empty() {
+            return (result, None);
+        }
+        changes.sort_by_key(|change| change.id());
+        let command = Command {
+            label: label.to_string(),
+            changes,
+        };
+        (result, Some(command))
+    }
+
+    /// The changes between two states of the task list, `None` if they are the same
+    pub fn diff(
+        label: &str,
+        before: &HashMap<Uuid, Task>,
+        after: &HashMap<Uuid, Task>,
+    ) -> Option<Command> {
+        let mut changes: Vec<Change> = before
+            .values()
+            .filter(|task| after.get(&task.get_uuid()) != Some(task))
+            .map(|task| Change {
+                before: Some(task.clone()),
+                after: after.get(&task.get_uuid()).cloned(),
+            })
+            .chain(
+                after
+                    .values()
+                    .filter(|task| !before.contains_key(&task.get_uuid()))
+                    .map(|task| Change {
+                        before: None,
+                        after: Some(task.clone()),
+                    }),
+            )
+            .collect();
+        if changes.is_empty() {
+            return None;
+        }
+        changes.sort_by_key(|change| change.id());
+        Some(Command {
+            label: label.to_string(),
+            changes,
+        })
+    }
+
+    pub fn apply(&self, tasks: &mut TaskCollection) {
+        for change in &self.changes {
+            set(tasks, change.id(), &change.after);
+        }
+    }
+
+    pub fn revert(&self, tasks: &mut TaskCollection) {
+        for change in &self.changes {
+            set(tasks, change.id(), &change.before);
+        }
+    }
+}
+
+fn set(tasks: &mut TaskCollection, id: Uuid, task: &Option<Task>) {
+    match task {
+        Some(task) => tasks.insert(task.clone()),
+        None => tasks.remove(id),
+    };
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+}
+
+impl History {
+    /// Adds an already applied command, dropping everything that could be redone
+    pub fn record(&mut self, command: Command) {
+        self.redo.clear();
+        self.undo.push(command);
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn undo(&mut self, tasks: &mut TaskCollection) -> Option<&Command> {
+        let command = self.undo.pop()?;
+        command.revert(tasks);
+        self.redo.push(command);
+        self.redo.last()
+    }
+
+    pub fn redo(&mut self, tasks: &mut TaskCollection) -> Option<&Command> {
+        let command = self.redo.pop()?;
+        command.apply(tasks);
+        self.undo.push(command);
+        self.undo.last()
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo.last().map(|command| command.label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last().map(|command| command.label.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn execute(
+        history: &mut History,
+        tasks: &mut TaskCollection,
+        label: &str,
+        f: impl FnOnce(&mut TaskCollection),
+    ) {
+        if let (_, Some(command)) = Command::execute(label, tasks, f) {
+            history.record(command);
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut tasks = TaskCollection::new();
+        let mut history = History::default();
+        let task = Task::default();
+        let id = task.get_uuid();
+
+        execute(&mut history, &mut tasks, "Create task", |tasks| {
+            tasks.insert(task.clone());
+        });
+        execute(&mut history, &mut tasks, "Start task", |tasks| {
+            tasks.start(id).unwrap();
+        });
+        // Commands without an effect are not recorded
+        assert!(Command::execute("Nothing", &mut tasks, |_| ()).1.is_none());
+        assert_eq!(history.undo_label(), Some("Start task"));
+
+        assert_eq!(history.undo(&mut tasks).unwrap().label, "Start task");
+        assert_eq!(tasks[&id], task);
+        history.undo(&mut tasks);
+        assert!(tasks.is_empty());
+        assert!(history.undo(&mut tasks).is_none());
+
+        history.redo(&mut tasks);
+        history.redo(&mut tasks);
+        assert!(tasks[&id].is_started());
+        assert_eq!(history.redo_label(), None);
+    }
+
+    #[test]
+    fn only_touched_tasks() {
+        let [parent, child, other] = [(); 3].map(|_| Task::default());
+        let (parent_id, child_id) = (parent.get_uuid(), child.get_uuid());
+        let mut tasks: TaskCollection = [parent, child, other].into_iter().collect();
+        tasks.link(parent_id, child_id).unwrap();
+        let linked = tasks[&parent_id].clone();
+
+        let (_, command) = Command::execute("Delete task", &mut tasks, |tasks| {
+            tasks.delete(child_id);
+        });
+        let changes = command.unwrap().changes;
+        assert_eq!(changes.len(), 2);
+        let unlinked = changes.iter().find(|c| c.id() == parent_id).unwrap();
+        assert_eq!(unlinked.before, Some(linked));
+        assert!(changes
+            .iter()
+            .any(|c| c.id() == child_id && c.after.is_none()));
+    }
+
+    #[test]
+    fn record_drops_redo() {
+        let mut tasks = TaskCollection::new();
+        let mut history = History::default();
+        for _ in 0..HISTORY_LIMIT + 5 {
+            execute(&mut history, &mut tasks, "Create task", |tasks| {
+                tasks.insert(Task::default());
+            });
+        }
+        while history.undo(&mut tasks).is_some() {}
+        assert_eq!(tasks.len(), 5);
+
+        history.redo(&mut tasks);
+        execute(&mut history, &mut tasks, "Clear", |tasks| tasks.clear());
+        assert_eq!(history.redo_label(), None);
+        history.undo(&mut tasks);
+        assert_eq!(tasks.len(), 6);
+    }
+}
@@ -0,0 +1,83 @@
+//! Errors of the task library, front-ends show them instead of crashing
+
+use std::fmt;
+use std::io;
+
+use uuid::Uuid;
+
+use crate::formats::ParseError;
+
+#[derive(Debug)]
+pub enum TaskmanError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Parse(ParseError),
+    Database(rusqlite::Error),
+    TaskNotFound(Uuid),
+    SubtaskExists(Uuid),
+    SubtaskMissing(Uuid),
+    /// Linking would make a task a subtask of itself
+    Cycle {
+        parent: Uuid,
+        child: Uuid,
+    },
+    Notification(String),
+}
+
+pub type Result<T> = std::result::Result<T, TaskmanError>;
+
+impl fmt::Display for TaskmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskmanError::Io(err) => write!(f, "{}", err),
+            TaskmanError::Json(err) => write!(f, "{}", err),
+            TaskmanError::Parse(err) => write!(f, "{}", err),
+            TaskmanError::Database(err) => write!(f, "{}", err),
+            TaskmanError::TaskNotFound(id) => write!(f, "No task with id {}", id),
+            TaskmanError::SubtaskExists(id) => write!(f, "{} is already a subtask", id),
+            TaskmanError::SubtaskMissing(id) => write!(f, "{} is not a subtask", id),
+            TaskmanError::Cycle { parent, child } => write!(
+                f,
+                "{} can not be a subtask of {}, it would become a subtask of itself",
+                child, parent
+            ),
+            TaskmanError::Notification(msg) => write!(f, "Could not show notification: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TaskmanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TaskmanError::Io(err) => Some(err),
+            TaskmanError::Json(err) => Some(err),
+            TaskmanError::Parse(err) => Some(err),
+            TaskmanError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TaskmanError {
+    fn from(err: io::Error) -> Self {
+        TaskmanError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for TaskmanError {
+    fn from(err: serde_json::Error) -> Self {
+        TaskmanError::Json(err)
+    }
+}
+
+impl From<ParseError> for TaskmanError {
+    fn from(err: ParseError) -> Self {
+        TaskmanError::Parse(err)
+    }
+}
+
+impl From<rusqlite::Error> for TaskmanError {
+    fn from(err: rusqlite::Error) -> Self {
+        TaskmanError::Database(err)
+    }
+}
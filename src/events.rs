@@ -0,0 +1,308 @@
+//! Append-only log of every change made to a task
+//!
+//! Each line of the log is one JSON [`Event`]. Events carry the task as it was after the change,
+//! so the task list can be rebuilt by replaying the log.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::command::Change;
+use crate::task::Task;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    /// The task existed before the log was started
+    Recorded,
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Described,
+    Started,
+    Finished,
+    Linked {
+        subtask: String,
+    },
+    Unlinked {
+        subtask: String,
+    },
+    /// Any other field changed
+    Updated,
+    Deleted,
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::Created => write!(f, "created"),
+            EventKind::Recorded => write!(f, "recorded"),
+            EventKind::Renamed { from, to } => write!(f, "renamed from '{}' to '{}'", from, to),
+            EventKind::Described => write!(f, "changed description"),
+            EventKind::Started => write!(f, "started"),
+            EventKind::Finished => write!(f, "finished"),
+            EventKind::Linked { subtask } => write!(f, "added subtask '{}'", subtask),
+            EventKind::Unlinked { subtask } => write!(f, "removed subtask '{}'", subtask),
+            EventKind::Updated => write!(f, "updated"),
+            EventKind::Deleted => write!(f, "deleted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    pub author: String,
+    pub id: Uuid,
+    pub kinds: Vec<EventKind>,
+    /// The task after the change, `None` once it is deleted
+    pub task: Option<Task>,
+}
+
+impl Event {
+    pub fn new(change: &Change, time: DateTime<Utc>, author: &str) -> Self {
+        Self {
+            time,
+            author: author.to_string(),
+            id: change.id(),
+            kinds: kinds(change),
+            task: change.after.clone(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        self.kinds
+            .iter()
+            .map(|kind| kind.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+fn kinds(change: &Change) -> Vec<EventKind> {
+    let (before, after) = match (&change.before, &change.after) {
+        (None, _) => return vec![EventKind::Created],
+        (_, None) => return vec![EventKind::Deleted],
+        (Some(before), Some(after)) => (before, after),
+    };
+
+    let mut kinds = Vec::new();
+    // Whatever is left different after copying the described fields is an update
+    let mut rest = before.clone();
+    if before.name != after.name {
+        kinds.push(EventKind::Renamed {
+            from: before.name.clone(),
+            to: after.name.clone(),
+        });
+        rest.name = after.name.clone();
+    }
+    if before.description != after.description {
+        kinds.push(EventKind::Described);
+        rest.description = after.description.clone();
+    }
+    if before.started.is_none() && after.started.is_some() {
+        kinds.push(EventKind::Started);
+        rest.started = after.started;
+        rest.intervals = after.intervals.clone();
+    }
+    if before.finished.is_none() && after.finished.is_some() {
+        kinds.push(EventKind::Finished);
+        rest.finished = after.finished;
+        rest.intervals = after.intervals.clone();
+    }
+    let subtasks = |task: &Task| task.subtasks.clone().unwrap_or_default();
+    let (old, new) = (subtasks(before), subtasks(after));
+    for (id, name) in new.iter().filter(|(id, _)| !before.has_subtask(*id)) {
+        kinds.push(EventKind::Linked {
+            subtask: name.clone(),
+        });
+        rest.add_subtask(*id, name.clone()).ok();
+    }
+    for (id, name) in old.iter().filter(|(id, _)| !after.has_subtask(*id)) {
+        kinds.push(EventKind::Unlinked {
+            subtask: name.clone(),
+        });
+        rest.remove_subtask(*id).ok();
+    }
+    if &rest != after {
+        kinds.push(EventKind::Updated);
+    }
+    kinds
+}
+
+/// The user recorded as author of changes made in this session
+pub fn current_author() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Replays events in order, returning the resulting task list
+pub fn rebuild<'a>(events: impl IntoIterator<Item = &'a Event>) -> HashMap<Uuid, Task> {
+    let mut tasks = HashMap::new();
+    for event in events {
+        match &event.task {
+            Some(task) => tasks.insert(event.id, task.clone()),
+            None => tasks.remove(&event.id),
+        };
+    }
+    tasks
+}
+
+pub struct EventLog {
+    path: PathBuf,
+}
+
+impl EventLog {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn append(&self, events: &[Event]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event).expect("events always serialize"));
+            lines.push('\n');
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // A single write so a crash can at most cut off the last line
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// All events in the order they were appended, a cut off last line is ignored
+    pub fn read(&self) -> io::Result<Vec<Event>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let lines = io::BufReader::new(file)
+            .lines()
+            .collect::<io::Result<Vec<String>>>()?;
+        let mut events = Vec::new();
+        for (idx, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(event) => events.push(event),
+                Err(_) if idx + 1 == lines.len() => break,
+                Err(err) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", idx + 1, err),
+                    ))
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn task_history(&self, id: Uuid) -> io::Result<Vec<Event>> {
+        Ok(self
+            .read()?
+            .into_iter()
+            .filter(|event| event.id == id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::storage::TempDir;
+
+    #[test]
+    fn describe_changes() {
+        let mut before = Task::default();
+        let sub = Task::default();
+        before
+            .add_subtask(sub.get_uuid(), "Old".to_string())
+            .unwrap();
+        let mut after = before.clone();
+        after.name = "Renamed".to_string();
+        after.start();
+        after.remove_subtask(sub.get_uuid()).unwrap();
+        after.tags.push("home".to_string());
+
+        let change = Change {
+            before: Some(before.clone()),
+            after: Some(after),
+        };
+        assert_eq!(
+            kinds(&change),
+            vec![
+                EventKind::Renamed {
+                    from: "New Task".to_string(),
+                    to: "Renamed".to_string()
+                },
+                EventKind::Started,
+                EventKind::Unlinked {
+                    subtask: "Old".to_string()
+                },
+                EventKind::Updated,
+            ]
+        );
+        let deleted = Change {
+            before: Some(before),
+            after: None,
+        };
+        assert_eq!(kinds(&deleted), vec![EventKind::Deleted]);
+    }
+
+    #[test]
+    fn append_and_rebuild() {
+        let dir = TempDir::new();
+        let log = EventLog::new(&dir.join("events.jsonl"));
+        let kept = Task::default();
+        let deleted = Task::default();
+
+        let now = Utc::now();
+        let events: Vec<Event> = [
+            (None, Some(kept.clone())),
+            (None, Some(deleted.clone())),
+            (Some(deleted), None),
+        ]
+        .into_iter()
+        .map(|(before, after)| Event::new(&Change { before, after }, now, "tester"))
+        .collect();
+        log.append(&events[..2]).unwrap();
+        log.append(&events[2..]).unwrap();
+        // Cut off by a crash while appending
+        fs::OpenOptions::new()
+            .append(true)
+            .open(log.path())
+            .unwrap()
+            .write_all(b"{\"time\":")
+            .unwrap();
+
+        let read = log.read().unwrap();
+        assert_eq!(read, events);
+        assert_eq!(log.task_history(kept.get_uuid()).unwrap().len(), 1);
+        let tasks = rebuild(&read);
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[&kept.get_uuid()], kept);
+    }
+}
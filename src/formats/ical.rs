@@ -0,0 +1,457 @@
+//! Import and export of tasks as iCalendar (RFC 5545) VTODO entries
+//!
+//! Subtask links are written as `RELATED-TO;RELTYPE=CHILD` on the parent. On import both
+//! `RELTYPE=CHILD` on the parent and `RELTYPE=PARENT` (the default) on the child are understood,
+//! as long as both ends of the link are part of the imported file.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+use super::ParseError;
+use crate::task::Task;
+
+const PRODID: &str = "-//taskman//taskman//EN";
+const UTC_FMT: &str = "%Y%m%dT%H%M%SZ";
+const LOCAL_FMT: &str = "%Y%m%dT%H%M%S";
+const DATE_FMT: &str = "%Y%m%d";
+/// Maximum length of a content line in octets, excluding the line break
+const LINE_LEN: usize = 75;
+
+pub fn export(tasks: &[&Task]) -> String {
+    let mut out = String::new();
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, &format!("PRODID:{}", PRODID));
+    // DTSTAMP is when the entry was written, the creation time goes into CREATED
+    let stamp = Utc::now().format(UTC_FMT).to_string();
+    for task in tasks {
+        write_line(&mut out, "BEGIN:VTODO");
+        write_line(&mut out, &format!("UID:{}", task.get_uuid()));
+        write_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        write_line(
+            &mut out,
+            &format!("CREATED:{}", task.get_creation_time().format(UTC_FMT)),
+        );
+        write_line(&mut out, &format!("SUMMARY:{}", escape(&task.name)));
+        if !task.description.is_empty() {
+            write_line(
+                &mut out,
+                &format!("DESCRIPTION:{}", escape(&task.description)),
+            );
+        }
+        if let Some(started) = task.get_started() {
+            write_line(&mut out, &format!("DTSTART:{}", started.format(UTC_FMT)));
+        }
+        if let Some(due) = task.due {
+            write_line(&mut out, &format!("DUE:{}", due.format(UTC_FMT)));
+        }
+        if let Some(finished) = task.get_finished() {
+            write_line(&mut out, &format!("COMPLETED:{}", finished.format(UTC_FMT)));
+        }
+        write_line(
+            &mut out,
+            match (task.is_started(), task.is_finished()) {
+                (_, true) => "STATUS:COMPLETED",
+                (true, false) => "STATUS:IN-PROCESS",
+                (false, false) => "STATUS:NEEDS-ACTION",
+            },
+        );
+        if let Some(subtasks) = task.get_subtasks() {
+            for (id, _) in subtasks {
+                write_line(&mut out, &format!("RELATED-TO;RELTYPE=CHILD:{}", id));
+            }
+        }
+        write_line(&mut out, "END:VTODO");
+    }
+    write_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+pub fn import(input: &str) -> Result<Vec<Task>, ParseError> {
+    let mut todos: Vec<Todo> = Vec::new();
+    let mut components: Vec<String> = Vec::new();
+
+    for (line_nr, line) in unfold(input) {
+        let prop = Property::parse(&line).ok_or_else(|| {
+            ParseError::new(line_nr, format!("malformed content line '{}'", line))
+        })?;
+
+        match prop.name.as_str() {
+            "BEGIN" => {
+                let component = prop.value.to_uppercase();
+                if component == "VTODO" {
+                    todos.push(Todo::new(line_nr));
+                }
+                components.push(component);
+            }
+            "END" => {
+                let component = prop.value.to_uppercase();
+                if components.pop().as_ref() != Some(&component) {
+                    return Err(ParseError::new(
+                        line_nr,
+                        format!("unexpected END:{}", prop.value),
+                    ));
+                }
+            }
+            _ => {
+                if components.last().map(|c| c.as_str()) == Some("VTODO") {
+                    todos
+                        .last_mut()
+                        .unwrap()
+                        .add_property(prop)
+                        .map_err(|msg| ParseError::new(line_nr, msg))?;
+                }
+            }
+        }
+    }
+
+    if let Some(component) = components.pop() {
+        return Err(ParseError::new(
+            input.lines().count(),
+            format!("missing END:{}", component),
+        ));
+    }
+
+    let ids: HashMap<String, Uuid> = todos
+        .iter()
+        .filter_map(|todo| todo.uid.clone())
+        .map(|uid| {
+            let id = Uuid::parse_str(&uid).unwrap_or_else(|_| Uuid::new_v4());
+            (uid, id)
+        })
+        .collect();
+
+    let mut tasks: Vec<Task> = todos.iter().map(|todo| todo.to_task(&ids)).collect();
+
+    let mut links: Vec<(Uuid, Uuid)> = Vec::new();
+    for (todo, task) in todos.iter().zip(tasks.iter()) {
+        for (reltype, uid) in &todo.related {
+            if let Some(other) = ids.get(uid) {
+                match reltype.as_str() {
+                    "CHILD" => links.push((task.get_uuid(), *other)),
+                    "PARENT" => links.push((*other, task.get_uuid())),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    let names: HashMap<Uuid, String> = tasks
+        .iter()
+        .map(|task| (task.get_uuid(), task.name.clone()))
+        .collect();
+    for (parent, child) in links {
+        if parent == child {
+            continue;
+        }
+        if let Some(task) = tasks.iter_mut().find(|task| task.get_uuid() == parent) {
+            // Links listed twice are kept once
+            task.add_subtask(child, names[&child].clone()).ok();
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Properties of a single VTODO component as read from the file
+struct Todo {
+    line: usize,
+    uid: Option<String>,
+    summary: Option<String>,
+    description: Option<String>,
+    created: Option<DateTime<Utc>>,
+    dtstamp: Option<DateTime<Utc>>,
+    dtstart: Option<DateTime<Utc>>,
+    due: Option<DateTime<Utc>>,
+    completed: Option<DateTime<Utc>>,
+    status: Option<String>,
+    related: Vec<(String, String)>,
+}
+
+impl Todo {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            uid: None,
+            summary: None,
+            description: None,
+            created: None,
+            dtstamp: None,
+            dtstart: None,
+            due: None,
+            completed: None,
+            status: None,
+            related: Vec::new(),
+        }
+    }
+
+    fn add_property(&mut self, prop: Property) -> Result<(), String> {
+        match prop.name.as_str() {
+            "UID" => self.uid = Some(prop.value),
+            "SUMMARY" => self.summary = Some(unescape(&prop.value)),
+            "DESCRIPTION" => self.description = Some(unescape(&prop.value)),
+            "CREATED" => self.created = Some(prop.datetime()?),
+            "DTSTAMP" => self.dtstamp = Some(prop.datetime()?),
+            "DTSTART" => self.dtstart = Some(prop.datetime()?),
+            "DUE" => self.due = Some(prop.datetime()?),
+            "COMPLETED" => self.completed = Some(prop.datetime()?),
+            "STATUS" => self.status = Some(prop.value.to_uppercase()),
+            "RELATED-TO" => {
+                let reltype = prop
+                    .param("RELTYPE")
+                    .map(|r| r.to_uppercase())
+                    .unwrap_or_else(|| "PARENT".to_string());
+                self.related.push((reltype, prop.value));
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn to_task(&self, ids: &HashMap<String, Uuid>) -> Task {
+        let mut task = Task {
+            creation_time: self.created.unwrap_or_else(Utc::now),
+            name: self
+                .summary
+                .clone()
+                .unwrap_or_else(|| format!("Imported task (line {})", self.line)),
+            description: self.description.clone().unwrap_or_default(),
+            due: self.due,
+            ..Default::default()
+        };
+        if let Some(id) = self.uid.as_ref().and_then(|uid| ids.get(uid)) {
+            task.id = *id;
+        }
+
+        let completed = match (self.completed, self.status.as_deref()) {
+            (Some(completed), _) => Some(completed),
+            (None, Some("COMPLETED")) => Some(self.dtstamp.unwrap_or(task.creation_time)),
+            (None, _) => None,
+        };
+        // DTSTART of a task nobody works on yet is when it is planned to start
+        let in_progress = completed.is_some() || self.status.as_deref() == Some("IN-PROCESS");
+        task.set_times(self.dtstart.filter(|_| in_progress), completed);
+        task
+    }
+}
+
+/// A single unfolded content line: `NAME;PARAM=VALUE:value`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        let mut in_quotes = false;
+        let mut value_start = None;
+        for (idx, c) in line.char_indices() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ':' if !in_quotes => {
+                    value_start = Some(idx);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        let value_start = value_start?;
+        let mut head = line[..value_start].split(';');
+        let name = head.next()?.trim().to_uppercase();
+        if name.is_empty() {
+            return None;
+        }
+        let params = head
+            .filter_map(|param| param.split_once('='))
+            .map(|(k, v)| (k.to_uppercase(), v.trim_matches('"').to_string()))
+            .collect();
+        Some(Self {
+            name,
+            params,
+            value: line[value_start + 1..].to_string(),
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Floating times and times with a `TZID` are interpreted as local time, dates as local
+    /// midnight.
+    fn datetime(&self) -> Result<DateTime<Utc>, String> {
+        let value = self.value.trim();
+        let invalid = || format!("invalid date '{}' in {}", value, self.name);
+        if self.param("VALUE") == Some("DATE") || value.len() == 8 {
+            let date = NaiveDate::parse_from_str(value, DATE_FMT).map_err(|_| invalid())?;
+            local_to_utc(date.and_hms_opt(0, 0, 0).unwrap()).ok_or_else(invalid)
+        } else if value.ends_with('Z') {
+            NaiveDateTime::parse_from_str(value, UTC_FMT)
+                .map(|dt| Utc.from_utc_datetime(&dt))
+                .map_err(|_| invalid())
+        } else {
+            let dt = NaiveDateTime::parse_from_str(value, LOCAL_FMT).map_err(|_| invalid())?;
+            local_to_utc(dt).ok_or_else(invalid)
+        }
+    }
+}
+
+fn local_to_utc(dt: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Local
+        .from_local_datetime(&dt)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Joins folded lines, returning each logical line with the number of its first physical line
+fn unfold(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let (Some(rest), Some((_, last))) = (
+            line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            last.push_str(rest);
+        } else if !line.trim().is_empty() {
+            lines.push((idx + 1, line.to_string()));
+        }
+    }
+    lines
+}
+
+/// Writes a content line, folding it after [`LINE_LEN`] octets
+fn write_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > LINE_LEN {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => (),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut sub = Task {
+            name: "Sub, with; special\\chars".to_string(),
+            description: "Line one\nLine two".to_string(),
+            ..Default::default()
+        };
+        sub.start();
+        sub.finish();
+        let mut parent = Task {
+            name: "A rather long task name that definitely needs to be folded across lines"
+                .to_string(),
+            due: Some(Utc.with_ymd_and_hms(2023, 2, 1, 12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        parent
+            .add_subtask(sub.get_uuid(), sub.name.clone())
+            .unwrap();
+
+        let exported = export(&[&parent, &sub]);
+        assert!(exported.lines().all(|l| l.len() <= LINE_LEN + 1));
+
+        let imported = import(&exported).unwrap();
+        assert_eq!(imported.len(), 2);
+        for (orig, imp) in [&parent, &sub].iter().zip(imported.iter()) {
+            assert_eq!(orig.get_uuid(), imp.get_uuid());
+            assert_eq!(orig.name, imp.name);
+            assert_eq!(orig.description, imp.description);
+            assert_eq!(orig.due, imp.due);
+            assert_eq!(orig.get_subtasks(), imp.get_subtasks());
+            assert_eq!(
+                orig.get_creation_time().timestamp(),
+                imp.get_creation_time().timestamp()
+            );
+            assert_eq!(orig.is_finished(), imp.is_finished());
+        }
+    }
+
+    #[test]
+    fn import_foreign() {
+        let input = "BEGIN:VCALENDAR\r\n\
+                     BEGIN:VTODO\r\n\
+                     UID:parent@example.com\r\n\
+                     DTSTAMP:20230110T080000Z\r\n\
+                     CREATED:20230102T080000Z\r\n\
+                     SUMMARY:Parent\r\n\
+                     DTSTART:20990101T090000Z\r\n\
+                     STATUS:NEEDS-ACTION\r\n\
+                     BEGIN:VALARM\r\n\
+                     SUMMARY:Not a task\r\n\
+                     END:VALARM\r\n\
+                     END:VTODO\r\n\
+                     BEGIN:VTODO\r\n\
+                     UID:child@example.com\r\n\
+                     SUMMARY:Ch\r\n ild\r\n\
+                     STATUS:COMPLETED\r\n\
+                     DUE;VALUE=DATE:20230105\r\n\
+                     RELATED-TO:parent@example.com\r\n\
+                     END:VTODO\r\n\
+                     END:VCALENDAR\r\n";
+        let tasks = import(input).unwrap();
+        assert_eq!(tasks[0].name, "Parent");
+        assert_eq!(
+            tasks[0].get_creation_time(),
+            Utc.with_ymd_and_hms(2023, 1, 2, 8, 0, 0).unwrap()
+        );
+        assert!(!tasks[0].is_started());
+        assert_eq!(tasks[1].name, "Child");
+        assert!(tasks[1].is_finished() && !tasks[1].is_started());
+        assert!(tasks[1].due.is_some());
+        assert!(tasks[0].has_subtask(tasks[1].get_uuid()));
+    }
+
+    #[test]
+    fn import_errors() {
+        let err = import("BEGIN:VTODO\nDUE:tomorrow\nEND:VTODO\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(import("BEGIN:VTODO\nSUMMARY:x\n").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use crate::schema::Document;
+use crate::task::Task;
+
+pub mod ical;
+pub mod org;
+pub mod taskwarrior;
+pub mod todotxt;
+
+/// File formats task lists can be imported from and exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    ICal,
+    TodoTxt,
+    Org,
+    Taskwarrior,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, msg: impl Into<String>) -> Self {
+        Self {
+            line,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Format {
+    pub const ALL: [Format; 5] = [
+        Format::Json,
+        Format::ICal,
+        Format::TodoTxt,
+        Format::Org,
+        Format::Taskwarrior,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::ICal => "iCalendar",
+            Format::TodoTxt => "todo.txt",
+            Format::Org => "Org mode",
+            Format::Taskwarrior => "Taskwarrior",
+        }
+    }
+
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["json"],
+            Format::ICal => &["ics", "ical"],
+            Format::TodoTxt => &["txt"],
+            Format::Org => &["org"],
+            Format::Taskwarrior => &["json"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        Format::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&ext.as_str()))
+    }
+
+    /// Guesses the format of a file from its extension and, for json files, its content
+    pub fn detect(path: &Path, input: &str) -> Option<Format> {
+        match Format::from_path(path)? {
+            Format::Json if taskwarrior::detect(input) => Some(Format::Taskwarrior),
+            format => Some(format),
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Result<Vec<Task>, ParseError> {
+        match self {
+            Format::Json => Document::load(input)
+                .map(|doc| doc.tasks)
+                .map_err(|err| ParseError::new(err.line(), err.to_string())),
+            Format::ICal => ical::import(input),
+            Format::TodoTxt => todotxt::import(input),
+            Format::Org => org::import(input),
+            Format::Taskwarrior => taskwarrior::import(input),
+        }
+    }
+
+    pub fn write(&self, tasks: &[&Task]) -> String {
+        match self {
+            Format::Json => {
+                Document::new(tasks.iter().map(|&task| task.clone()).collect()).to_json()
+            }
+            Format::ICal => ical::export(tasks),
+            Format::TodoTxt => todotxt::export(tasks),
+            Format::Org => org::export(tasks),
+            Format::Taskwarrior => taskwarrior::export(tasks),
+        }
+    }
+}
@@ -0,0 +1,799 @@
+//! Import and export of Emacs [Org mode](https://orgmode.org) files
+//!
+//! Headlines with a todo keyword become tasks, nested headlines their subtasks and `CLOCK` lines
+//! their time intervals, so tracked time shows up in the org agenda and clock reports. The task
+//! id, creation time and projects are kept in the `PROPERTIES` drawer.
+//!
+//! Org mode files are trees while subtasks can have several parents. On export every task is
+//! nested below the first parent it is encountered under, additional parents list it in their
+//! `SUBTASKS` property instead.
+//!
+//! Org timestamps stop at minutes, the exact times of intervals that do not fall on whole minutes
+//! are kept in the `CLOCKED` property. Description lines that would be read as planning, clock,
+//! drawer or keyword lines are escaped with a comma, as Org mode does in blocks.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Timelike, Utc};
+use uuid::Uuid;
+
+use super::ParseError;
+use crate::task::{Interval, Task};
+
+const TIMESTAMP_FMT: &str = "%Y-%m-%d %a %H:%M";
+const DEFAULT_OPEN: &[&str] = &["TODO"];
+const DEFAULT_DONE: &[&str] = &["DONE"];
+
+pub fn export(tasks: &[&Task]) -> String {
+    let by_id: HashMap<Uuid, &Task> = tasks.iter().map(|t| (t.get_uuid(), *t)).collect();
+    let is_subtask: HashSet<Uuid> = tasks
+        .iter()
+        .filter_map(|t| t.get_subtasks())
+        .flatten()
+        .map(|(id, _)| *id)
+        .filter(|id| by_id.contains_key(id))
+        .collect();
+
+    let mut outline: Vec<Node> = Vec::new();
+    let mut emitted: HashSet<Uuid> = HashSet::new();
+    // Tasks that are only part of subtask cycles have no root and are added afterwards
+    let roots = tasks
+        .iter()
+        .filter(|t| !is_subtask.contains(&t.get_uuid()))
+        .chain(tasks.iter());
+    for root in roots {
+        if !emitted.contains(&root.get_uuid()) {
+            build_outline(root, 1, &by_id, &mut emitted, &mut outline);
+        }
+    }
+
+    let mut out = String::new();
+    for node in outline {
+        write_node(&mut out, &node, by_id[&node.id]);
+    }
+    out
+}
+
+pub fn import(input: &str) -> Result<Vec<Task>, ParseError> {
+    let mut open_keywords: Vec<String> = DEFAULT_OPEN.iter().map(|k| k.to_string()).collect();
+    let mut done_keywords: Vec<String> = DEFAULT_DONE.iter().map(|k| k.to_string()).collect();
+    let mut custom_keywords = false;
+
+    let mut entries: Vec<Entry> = Vec::new();
+    // Level and entry of the enclosing headlines, `None` for headlines that are no tasks
+    let mut outline: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut drawer: Option<String> = None;
+
+    for (idx, line) in input.lines().enumerate() {
+        let line_nr = idx + 1;
+        let err = |msg: String| ParseError::new(line_nr, msg);
+
+        if let Some(level) = headline_level(line) {
+            if let Some(name) = drawer.take() {
+                return Err(err(format!("drawer :{}: is missing its :END:", name)));
+            }
+            while outline.last().is_some_and(|(l, _)| *l >= level) {
+                outline.pop();
+            }
+            let parent = outline.iter().rev().find_map(|(_, entry)| *entry);
+            let entry = Entry::parse_headline(
+                &line[level..],
+                line_nr,
+                parent,
+                &open_keywords,
+                &done_keywords,
+            );
+            outline.push((level, entry.as_ref().map(|_| entries.len())));
+            entries.extend(entry);
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if let Some(keywords) = todo_keywords(trimmed) {
+            if !custom_keywords {
+                open_keywords.clear();
+                done_keywords.clear();
+                custom_keywords = true;
+            }
+            let (open, done) = keywords;
+            open_keywords.extend(open);
+            done_keywords.extend(done);
+            continue;
+        }
+
+        // Everything before the first headline and below headlines that are no tasks is ignored
+        let Some(entry) = outline
+            .last()
+            .and_then(|(_, entry)| *entry)
+            .map(|idx| &mut entries[idx])
+        else {
+            continue;
+        };
+
+        if let Some(name) = &drawer {
+            if trimmed.eq_ignore_ascii_case(":END:") {
+                drawer = None;
+            } else if let Some(clock) = trimmed.strip_prefix("CLOCK:") {
+                entry.intervals.push(parse_clock(clock).map_err(err)?);
+            } else if name == "PROPERTIES" {
+                if let Some((key, value)) = parse_property(trimmed) {
+                    entry.properties.insert(key, value);
+                }
+            }
+        } else if let Some(name) = drawer_start(trimmed) {
+            drawer = Some(name);
+        } else if let Some(clock) = trimmed.strip_prefix("CLOCK:") {
+            entry.intervals.push(parse_clock(clock).map_err(err)?);
+        } else if is_planning(trimmed) {
+            entry.parse_planning(trimmed).map_err(err)?;
+        } else {
+            entry.body.push(unescape_line(line));
+        }
+    }
+    if let Some(name) = drawer {
+        return Err(ParseError::new(
+            input.lines().count(),
+            format!("drawer :{}: is missing its :END:", name),
+        ));
+    }
+
+    let mut ids: HashMap<String, Uuid> = HashMap::new();
+    for entry in &entries {
+        if let Some(id) = entry.properties.get("ID") {
+            ids.entry(id.clone())
+                .or_insert_with(|| Uuid::parse_str(id).unwrap_or_else(|_| Uuid::new_v4()));
+        }
+    }
+
+    // The same task may appear more than once, only the first occurrence is used
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut entry_task: Vec<Uuid> = Vec::new();
+    let mut seen: HashSet<Uuid> = HashSet::new();
+    for entry in &entries {
+        let task = entry
+            .to_task(&ids)
+            .map_err(|msg| ParseError::new(entry.line, msg))?;
+        entry_task.push(task.get_uuid());
+        if seen.insert(task.get_uuid()) {
+            tasks.push(task);
+        }
+    }
+
+    let mut links: Vec<(Uuid, Uuid)> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        if let Some(parent) = entry.parent {
+            links.push((entry_task[parent], entry_task[idx]));
+        }
+        if let Some(subtasks) = entry.properties.get("SUBTASKS") {
+            links.extend(
+                subtasks
+                    .split_whitespace()
+                    .filter_map(|id| ids.get(id))
+                    .map(|child| (entry_task[idx], *child)),
+            );
+        }
+    }
+
+    let names: HashMap<Uuid, String> = tasks
+        .iter()
+        .map(|task| (task.get_uuid(), task.name.clone()))
+        .collect();
+    for (parent, child) in links {
+        if parent == child || !names.contains_key(&child) {
+            continue;
+        }
+        if let Some(task) = tasks.iter_mut().find(|task| task.get_uuid() == parent) {
+            // Links listed twice are kept once
+            task.add_subtask(child, names[&child].clone()).ok();
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Position of a task in the exported outline
+struct Node {
+    id: Uuid,
+    level: usize,
+    /// Subtasks not nested below this headline
+    linked: Vec<Uuid>,
+}
+
+fn build_outline(
+    task: &Task,
+    level: usize,
+    by_id: &HashMap<Uuid, &Task>,
+    emitted: &mut HashSet<Uuid>,
+    outline: &mut Vec<Node>,
+) {
+    emitted.insert(task.get_uuid());
+    let idx = outline.len();
+    outline.push(Node {
+        id: task.get_uuid(),
+        level,
+        linked: Vec::new(),
+    });
+
+    for (id, _) in task.get_subtasks().into_iter().flatten() {
+        let Some(subtask) = by_id.get(id) else {
+            continue;
+        };
+        if emitted.contains(id) {
+            outline[idx].linked.push(*id);
+        } else {
+            build_outline(subtask, level + 1, by_id, emitted, outline);
+        }
+    }
+}
+
+fn write_node(out: &mut String, node: &Node, task: &Task) {
+    let indent = " ".repeat(node.level + 1);
+
+    let mut headline = format!(
+        "{} {}",
+        "*".repeat(node.level),
+        if task.is_finished() { "DONE" } else { "TODO" }
+    );
+    if let Some(priority) = task.priority {
+        headline.push_str(&format!(" [#{}]", priority));
+    }
+    headline.push(' ');
+    headline.push_str(&task.name.replace('\n', " "));
+    if !task.tags.is_empty() {
+        headline.push_str(&format!(" :{}:", task.tags.join(":")));
+    }
+    out.push_str(&headline);
+    out.push('\n');
+
+    let mut planning: Vec<String> = Vec::new();
+    if let Some(finished) = task.get_finished() {
+        planning.push(format!("CLOSED: [{}]", format_timestamp(finished)));
+    }
+    if let Some(due) = task.due {
+        planning.push(format!("DEADLINE: <{}>", format_timestamp(due)));
+    }
+    if !planning.is_empty() {
+        out.push_str(&format!("{}{}\n", indent, planning.join(" ")));
+    }
+
+    out.push_str(&format!("{}:PROPERTIES:\n", indent));
+    out.push_str(&format!("{}:ID:       {}\n", indent, task.get_uuid()));
+    out.push_str(&format!(
+        "{}:CREATED:  [{}]\n",
+        indent,
+        format_timestamp(task.get_creation_time())
+    ));
+    if !task.projects.is_empty() {
+        out.push_str(&format!(
+            "{}:PROJECTS: {}\n",
+            indent,
+            task.projects.join(" ")
+        ));
+    }
+    if !node.linked.is_empty() {
+        let linked = node
+            .linked
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>();
+        out.push_str(&format!("{}:SUBTASKS: {}\n", indent, linked.join(" ")));
+    }
+    let exact: Vec<String> = task
+        .get_intervals()
+        .iter()
+        .filter(|i| !on_minute(i.start) || i.end.is_some_and(|end| !on_minute(end)))
+        .map(format_exact)
+        .collect();
+    if !exact.is_empty() {
+        out.push_str(&format!("{}:CLOCKED:  {}\n", indent, exact.join(" ")));
+    }
+    out.push_str(&format!("{}:END:\n", indent));
+
+    if !task.get_intervals().is_empty() {
+        out.push_str(&format!("{}:LOGBOOK:\n", indent));
+        // Org mode lists the most recent clock first
+        for interval in task.get_intervals().iter().rev() {
+            out.push_str(&format!("{}{}\n", indent, format_clock(interval)));
+        }
+        out.push_str(&format!("{}:END:\n", indent));
+    }
+
+    for line in task.description.lines() {
+        if line.trim().is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{}{}\n", indent, escape_line(line)));
+        }
+    }
+}
+
+/// A headline with a todo keyword as read from the file
+struct Entry {
+    line: usize,
+    parent: Option<usize>,
+    name: String,
+    done: bool,
+    priority: Option<char>,
+    tags: Vec<String>,
+    closed: Option<DateTime<Utc>>,
+    deadline: Option<DateTime<Utc>>,
+    properties: HashMap<String, String>,
+    intervals: Vec<Interval>,
+    body: Vec<String>,
+}
+
+impl Entry {
+    /// Parses the headline after its stars, returns `None` if it has no todo keyword
+    fn parse_headline(
+        headline: &str,
+        line: usize,
+        parent: Option<usize>,
+        open_keywords: &[String],
+        done_keywords: &[String],
+    ) -> Option<Self> {
+        let mut words: Vec<&str> = headline.split_whitespace().collect();
+        let keyword = *words.first()?;
+        let done = if done_keywords.iter().any(|k| k == keyword) {
+            true
+        } else if open_keywords.iter().any(|k| k == keyword) {
+            false
+        } else {
+            return None;
+        };
+        words.remove(0);
+
+        let mut priority = None;
+        if let Some(p) = words.first().and_then(|w| parse_priority_cookie(w)) {
+            priority = Some(p);
+            words.remove(0);
+        }
+
+        let mut tags = Vec::new();
+        if let Some(last) = words.last() {
+            if last.len() > 2 && last.starts_with(':') && last.ends_with(':') {
+                tags = last
+                    .trim_matches(':')
+                    .split(':')
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.to_string())
+                    .collect();
+                words.pop();
+            }
+        }
+
+        Some(Self {
+            line,
+            parent,
+            name: words.join(" "),
+            done,
+            priority,
+            tags,
+            closed: None,
+            deadline: None,
+            properties: HashMap::new(),
+            intervals: Vec::new(),
+            body: Vec::new(),
+        })
+    }
+
+    fn parse_planning(&mut self, line: &str) -> Result<(), String> {
+        let mut rest = line;
+        while let Some((keyword, after)) = rest.split_once(':') {
+            let after = after.trim_start();
+            let (close, end) = match after.chars().next() {
+                Some('<') => ('>', after.find('>')),
+                Some('[') => (']', after.find(']')),
+                _ => return Err(format!("invalid planning line '{}'", line)),
+            };
+            let end = end.ok_or_else(|| format!("unterminated timestamp, missing '{}'", close))?;
+            let timestamp = parse_timestamp(&after[..=end])?;
+            match keyword.trim() {
+                "CLOSED" => self.closed = Some(timestamp),
+                "DEADLINE" => self.deadline = Some(timestamp),
+                _ => (),
+            }
+            rest = &after[end + 1..];
+        }
+        Ok(())
+    }
+
+    fn to_task(&self, ids: &HashMap<String, Uuid>) -> Result<Task, String> {
+        let mut task = Task {
+            name: self.name.clone(),
+            description: dedent(&self.body),
+            due: self.deadline,
+            priority: self.priority,
+            projects: self
+                .properties
+                .get("PROJECTS")
+                .map(|p| p.split_whitespace().map(|p| p.to_string()).collect())
+                .unwrap_or_default(),
+            tags: self.tags.clone(),
+            ..Default::default()
+        };
+        if let Some(id) = self.properties.get("ID").and_then(|id| ids.get(id)) {
+            task.id = *id;
+        }
+        if let Some(created) = self.properties.get("CREATED") {
+            task.creation_time = parse_timestamp(created)?;
+        }
+
+        let exact: Vec<Interval> = self
+            .properties
+            .get("CLOCKED")
+            .map(|c| c.split_whitespace().map(parse_exact).collect())
+            .transpose()?
+            .unwrap_or_default();
+        let mut intervals = self.intervals.clone();
+        // Clocks changed in Org mode no longer match their exact times and are kept as they are
+        for interval in intervals.iter_mut() {
+            if let Some(found) = exact.iter().find(|e| {
+                same_minute(e.start, interval.start)
+                    && match (e.end, interval.end) {
+                        (Some(a), Some(b)) => same_minute(a, b),
+                        (a, b) => a.is_none() && b.is_none(),
+                    }
+            }) {
+                *interval = found.clone();
+            }
+        }
+        intervals.sort_by_key(|i| i.start);
+        let finished = if self.done {
+            let last_clock = intervals.iter().filter_map(|i| i.end).max();
+            Some(self.closed.or(last_clock).unwrap_or(task.creation_time))
+        } else {
+            None
+        };
+        if let Some(finished) = finished {
+            for interval in intervals.iter_mut().filter(|i| i.end.is_none()) {
+                interval.end = Some(finished.max(interval.start));
+            }
+        }
+        task.started = intervals.first().map(|i| i.start);
+        task.finished = finished;
+        task.intervals = intervals;
+        Ok(task)
+    }
+}
+
+fn headline_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    if level > 0 && line[level..].starts_with(' ') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// Parses `#+TODO: OPEN NEXT | DONE` lines into open and done keywords
+fn todo_keywords(line: &str) -> Option<(Vec<String>, Vec<String>)> {
+    let (key, value) = line.split_once(':')?;
+    if !["#+TODO", "#+SEQ_TODO", "#+TYP_TODO"].contains(&key.to_uppercase().as_str()) {
+        return None;
+    }
+    // Fast access keys like `TODO(t)` are not part of the keyword
+    let keywords = |s: &str| -> Vec<String> {
+        s.split_whitespace()
+            .map(|k| k.split('(').next().unwrap_or(k).to_string())
+            .collect()
+    };
+    match value.split_once('|') {
+        Some((open, done)) => Some((keywords(open), keywords(done))),
+        None => {
+            let mut open = keywords(value);
+            let done = open.pop().into_iter().collect();
+            Some((open, done))
+        }
+    }
+}
+
+fn drawer_start(line: &str) -> Option<String> {
+    let name = line.strip_prefix(':')?.strip_suffix(':')?;
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        Some(name.to_uppercase())
+    } else {
+        None
+    }
+}
+
+fn parse_property(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.strip_prefix(':')?.split_once(':')?;
+    Some((key.to_uppercase(), value.trim().to_string()))
+}
+
+fn is_planning(line: &str) -> bool {
+    ["CLOSED:", "DEADLINE:", "SCHEDULED:"]
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+}
+
+fn parse_priority_cookie(word: &str) -> Option<char> {
+    let mut chars = word.strip_prefix("[#")?.strip_suffix(']')?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Some(c),
+        _ => None,
+    }
+}
+
+/// Parses `[2023-01-02 Mon 10:00]--[2023-01-02 Mon 11:00] =>  1:00` or a running clock
+fn parse_clock(clock: &str) -> Result<Interval, String> {
+    let clock = clock.split("=>").next().unwrap_or(clock).trim();
+    match clock.split_once("--") {
+        Some((start, end)) => Ok(Interval {
+            start: parse_timestamp(start.trim())?,
+            end: Some(parse_timestamp(end.trim())?),
+        }),
+        None => Ok(Interval {
+            start: parse_timestamp(clock)?,
+            end: None,
+        }),
+    }
+}
+
+/// Parses active and inactive timestamps like `<2023-01-02 Mon 10:00 +1w>` as local time, the
+/// time of day defaults to midnight
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    let invalid = || format!("invalid timestamp '{}'", timestamp);
+    let inner = timestamp
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .or_else(|| {
+            timestamp
+                .strip_prefix('<')
+                .and_then(|t| t.strip_suffix('>'))
+        })
+        .ok_or_else(invalid)?;
+
+    let mut parts = inner.split_whitespace();
+    let date = parts
+        .next()
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .ok_or_else(invalid)?;
+    // Time ranges like `10:00-11:00` start at their first time
+    let time = parts
+        .filter_map(|p| NaiveTime::parse_from_str(p.split('-').next()?, "%H:%M").ok())
+        .next()
+        .unwrap_or_default();
+
+    Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(invalid)
+}
+
+fn format_timestamp(time: DateTime<Utc>) -> String {
+    DateTime::<Local>::from(time)
+        .format(TIMESTAMP_FMT)
+        .to_string()
+}
+
+fn format_clock(interval: &Interval) -> String {
+    match interval.end {
+        Some(end) => {
+            let minutes = (end - interval.start).num_minutes();
+            format!(
+                "CLOCK: [{}]--[{}] => {:2}:{:02}",
+                format_timestamp(interval.start),
+                format_timestamp(end),
+                minutes / 60,
+                minutes % 60
+            )
+        }
+        None => format!("CLOCK: [{}]", format_timestamp(interval.start)),
+    }
+}
+
+fn on_minute(time: DateTime<Utc>) -> bool {
+    time.second() == 0 && time.nanosecond() == 0
+}
+
+/// Whether `exact` is `time` as written in a timestamp
+fn same_minute(exact: DateTime<Utc>, time: DateTime<Utc>) -> bool {
+    (exact - time).num_seconds().abs() < 60
+}
+
+/// Writes `2023-01-02T10:00:15Z--2023-01-02T11:00:42.5Z`, or only the start of a running clock
+fn format_exact(interval: &Interval) -> String {
+    let format = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+    match interval.end {
+        Some(end) => format!("{}--{}", format(interval.start), format(end)),
+        None => format(interval.start),
+    }
+}
+
+fn parse_exact(exact: &str) -> Result<Interval, String> {
+    let parse = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|_| format!("invalid exact clock time '{}'", time))
+    };
+    match exact.split_once("--") {
+        Some((start, end)) => Ok(Interval {
+            start: parse(start)?,
+            end: Some(parse(end)?),
+        }),
+        None => Ok(Interval {
+            start: parse(exact)?,
+            end: None,
+        }),
+    }
+}
+
+/// Whether a description line would be read as something else, commas in front of such lines
+/// are escapes themselves
+fn is_special(line: &str) -> bool {
+    let line = line.trim_start();
+    match line.strip_prefix(',') {
+        Some(rest) => is_special(rest),
+        None => {
+            line.starts_with("CLOCK:")
+                || is_planning(line)
+                || drawer_start(line.trim_end()).is_some()
+                || todo_keywords(line.trim_end()).is_some()
+        }
+    }
+}
+
+fn escape_line(line: &str) -> String {
+    if is_special(line) {
+        format!(",{}", line)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Removes the comma [`escape_line`] puts in front, keeping the indentation
+fn unescape_line(line: &str) -> String {
+    let text = line.trim_start();
+    match text.strip_prefix(',') {
+        Some(rest) if is_special(rest) => format!("{}{}", &line[..line.len() - text.len()], rest),
+        _ => line.to_string(),
+    }
+}
+
+/// Removes the indentation common to all non-empty lines as well as surrounding empty lines
+fn dedent(lines: &[String]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn import_outline() {
+        let input = "#+TITLE: Things\n\
+                     Some preamble\n\
+                     * Project notes\n\
+                     ** TODO [#A] Parent task :work:urgent:\n\
+                     \x20  DEADLINE: <2023-01-10 Tue>\n\
+                     \x20  :LOGBOOK:\n\
+                     \x20  CLOCK: [2023-01-03 Tue 10:00]--[2023-01-03 Tue 11:30] =>  1:30\n\
+                     \x20  CLOCK: [2023-01-02 Mon 09:00]--[2023-01-02 Mon 09:30] =>  0:30\n\
+                     \x20  :END:\n\
+                     \x20  First line\n\
+                     \x20    indented\n\
+                     *** DONE Child\n\
+                     \x20   CLOSED: [2023-01-04 Wed 12:00]\n\
+                     *** Just a note\n\
+                     **** TODO Grandchild\n";
+        let tasks = import(input).unwrap();
+        assert_eq!(tasks.len(), 3);
+
+        let parent = &tasks[0];
+        assert_eq!(parent.name, "Parent task");
+        assert_eq!(parent.priority, Some('A'));
+        assert_eq!(parent.tags, vec!["work".to_string(), "urgent".to_string()]);
+        assert_eq!(parent.description, "First line\n  indented");
+        assert_eq!(parent.due, parse_timestamp("<2023-01-10 Tue>").ok());
+        assert_eq!(parent.get_intervals().len(), 2);
+        assert_eq!(parent.tracked_time(), chrono::Duration::minutes(120));
+        assert_eq!(
+            parent.get_started(),
+            parse_timestamp("[2023-01-02 Mon 09:00]").ok()
+        );
+        assert!(!parent.is_finished());
+
+        assert!(tasks[1].is_finished());
+        assert_eq!(
+            tasks[1].get_finished(),
+            parse_timestamp("[2023-01-04 Wed 12:00]").ok()
+        );
+        assert!(parent.has_subtask(tasks[1].get_uuid()));
+        // Headlines without keyword are skipped, their children belong to the closest task
+        assert!(parent.has_subtask(tasks[2].get_uuid()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "* TODO Root :home:\n\
+                     \x20 :PROPERTIES:\n\
+                     \x20 :ID:       2f1d4c6e-8a3b-4d5e-9f60-718293a4b5c6\n\
+                     \x20 :CREATED:  [2023-01-01 Sun 08:00]\n\
+                     \x20 :PROJECTS: garden\n\
+                     \x20 :END:\n\
+                     \x20 :LOGBOOK:\n\
+                     \x20 CLOCK: [2023-01-02 Mon 10:00]\n\
+                     \x20 :END:\n\
+                     \x20 Water the plants\n\
+                     ** DONE [#B] Shared\n\
+                     \x20  CLOSED: [2023-01-02 Mon 11:00]\n\
+                     \x20  :PROPERTIES:\n\
+                     \x20  :ID:       9a8b7c6d-5e4f-4321-8765-43210fedcba9\n\
+                     \x20  :CREATED:  [2023-01-01 Sun 09:00]\n\
+                     \x20  :END:\n\
+                     * TODO Other\n\
+                     \x20 :PROPERTIES:\n\
+                     \x20 :ID:       01234567-89ab-4def-8123-456789abcdef\n\
+                     \x20 :CREATED:  [2023-01-01 Sun 10:00]\n\
+                     \x20 :SUBTASKS: 9a8b7c6d-5e4f-4321-8765-43210fedcba9\n\
+                     \x20 :END:\n";
+        let tasks = import(input).unwrap();
+        assert!(tasks[0].is_started());
+        assert!(tasks[2].has_subtask(tasks[1].get_uuid()));
+
+        let exported = export(&tasks.iter().collect::<Vec<&Task>>());
+        let reimported = import(&exported).unwrap();
+        assert_eq!(tasks, reimported);
+    }
+
+    #[test]
+    fn exact_clocks_and_escapes() {
+        let mut task = Task {
+            description: "CLOCK: not a clock\n  DEADLINE: none\n,CLOSED: escaped\n:END:"
+                .to_string(),
+            ..Default::default()
+        };
+        let start = Utc.with_ymd_and_hms(2023, 1, 2, 10, 0, 15).unwrap();
+        task.set_times(
+            Some(start),
+            Some(start + chrono::Duration::milliseconds(4_321_500)),
+        );
+
+        let reimported = import(&export(&[&task])).unwrap();
+        assert_eq!(reimported[0].get_intervals(), task.get_intervals());
+        assert_eq!(reimported[0].description, task.description);
+        assert_eq!(reimported[0].due, None);
+
+        // A clock changed in Org mode no longer matches its exact time and is taken as written
+        let changed = export(&[&task]).replace("2023-01-02T10:00:15Z", "2023-01-02T08:00:15Z");
+        let reimported = import(&changed).unwrap();
+        assert_eq!(reimported[0].get_intervals()[0].start.second(), 0);
+    }
+
+    #[test]
+    fn custom_keywords() {
+        let input = "#+TODO: TODO(t) NEXT | DONE CANCELLED\n\
+                     * NEXT Soon\n\
+                     * CANCELLED Never\n\
+                     * DONE Done\n";
+        let tasks = import(input).unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert!(!tasks[0].is_finished());
+        assert!(tasks[1].is_finished());
+    }
+
+    #[test]
+    fn import_errors() {
+        let err = import("* TODO Task\n  :LOGBOOK:\n  CLOCK: [yesterday]\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(import("* TODO Task\n  :PROPERTIES:\n").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+//! Import of `task export` JSON and export in the format understood by `task import`
+//!
+//! Taskwarrior's `depends` become subtasks, its annotations the description. Deleted tasks are
+//! not imported. Taskwarrior only knows a single project, so all but the first project of a task
+//! are lost on export.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ParseError;
+use crate::task::Task;
+
+const DATE_FMT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TwTask {
+    uuid: String,
+    description: String,
+    status: String,
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    entry: Option<DateTime<Utc>>,
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    start: Option<DateTime<Utc>>,
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    end: Option<DateTime<Utc>>,
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    due: Option<DateTime<Utc>>,
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_depends",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    depends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Annotation {
+    #[serde(default, with = "date", skip_serializing_if = "Option::is_none")]
+    entry: Option<DateTime<Utc>>,
+    description: String,
+}
+
+pub fn export(tasks: &[&Task]) -> String {
+    let tw_tasks: Vec<TwTask> = tasks
+        .iter()
+        .map(|task| TwTask {
+            uuid: task.get_uuid().to_string(),
+            description: task.name.clone(),
+            status: if task.is_finished() {
+                "completed".to_string()
+            } else {
+                "pending".to_string()
+            },
+            entry: Some(task.get_creation_time()),
+            // Taskwarrior treats every task with a start as active
+            start: task.get_started().filter(|_| task.is_running()),
+            end: task.get_finished(),
+            due: task.due,
+            modified: None,
+            project: task.projects.first().cloned(),
+            priority: task.priority.map(|p| {
+                match p {
+                    'A' => "H",
+                    'B' => "M",
+                    _ => "L",
+                }
+                .to_string()
+            }),
+            tags: task.tags.clone(),
+            depends: task
+                .get_subtasks()
+                .into_iter()
+                .flatten()
+                .map(|(id, _)| id.to_string())
+                .collect(),
+            annotations: if task.description.is_empty() {
+                Vec::new()
+            } else {
+                vec![Annotation {
+                    entry: Some(task.get_creation_time()),
+                    description: task.description.clone(),
+                }]
+            },
+        })
+        .collect();
+    serde_json::to_string(&tw_tasks).expect("tasks always serialize")
+}
+
+pub fn import(input: &str) -> Result<Vec<Task>, ParseError> {
+    let tw_tasks = parse(input)?;
+
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut depends: Vec<(Uuid, Vec<String>)> = Vec::new();
+    for (idx, tw_task) in tw_tasks.into_iter().enumerate() {
+        if tw_task.status == "deleted" {
+            continue;
+        }
+        let id = Uuid::parse_str(&tw_task.uuid).map_err(|err| {
+            ParseError::new(
+                line_of(input, &tw_task.uuid),
+                format!("task {}: invalid uuid: {}", idx + 1, err),
+            )
+        })?;
+
+        let mut task = Task {
+            id,
+            name: tw_task.description,
+            description: tw_task
+                .annotations
+                .into_iter()
+                .map(|a| a.description)
+                .collect::<Vec<String>>()
+                .join("\n"),
+            due: tw_task.due,
+            priority: tw_task.priority.and_then(|p| match p.as_str() {
+                "H" => Some('A'),
+                "M" => Some('B'),
+                "L" => Some('C'),
+                _ => None,
+            }),
+            projects: tw_task.project.into_iter().collect(),
+            tags: tw_task.tags,
+            ..Default::default()
+        };
+        if let Some(entry) = tw_task.entry {
+            task.creation_time = entry;
+        }
+        let finished = if tw_task.status == "completed" {
+            Some(
+                tw_task
+                    .end
+                    .or(tw_task.modified)
+                    .unwrap_or(task.creation_time),
+            )
+        } else {
+            None
+        };
+        task.set_times(tw_task.start, finished);
+
+        depends.push((id, tw_task.depends));
+        tasks.push(task);
+    }
+
+    let names: HashMap<Uuid, String> = tasks
+        .iter()
+        .map(|task| (task.get_uuid(), task.name.clone()))
+        .collect();
+    for (task, (id, deps)) in tasks.iter_mut().zip(depends) {
+        for dep in deps.iter().filter_map(|d| Uuid::parse_str(d).ok()) {
+            if dep != id && names.contains_key(&dep) {
+                // Dependencies listed twice are kept once
+                task.add_subtask(dep, names[&dep].clone()).ok();
+            }
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Whether the input looks like a Taskwarrior export rather than a taskman task list
+pub fn detect(input: &str) -> bool {
+    let first = input.trim_start().trim_start_matches('[').trim_start();
+    first.starts_with('{')
+        && serde_json::Deserializer::from_str(first)
+            .into_iter::<serde_json::Value>()
+            .next()
+            .and_then(|value| value.ok())
+            .is_some_and(|value| value.get("uuid").is_some())
+}
+
+/// `task export` writes a JSON array, older versions and hooks one object per line
+fn parse(input: &str) -> Result<Vec<TwTask>, ParseError> {
+    let to_parse_error = |err: serde_json::Error| ParseError::new(err.line(), err.to_string());
+    if input.trim_start().starts_with('[') {
+        serde_json::from_str(input).map_err(to_parse_error)
+    } else {
+        serde_json::Deserializer::from_str(input)
+            .into_iter::<TwTask>()
+            .collect::<Result<Vec<TwTask>, _>>()
+            .map_err(to_parse_error)
+    }
+}
+
+/// The line the JSON string `value` is first written on, the first one if it is escaped
+fn line_of(input: &str, value: &str) -> usize {
+    let quoted = serde_json::to_string(value).expect("strings always serialize");
+    input
+        .find(&quoted)
+        .map_or(1, |pos| input[..pos].matches('\n').count() + 1)
+}
+
+/// Older Taskwarrior versions write dependencies as a single comma separated string
+fn deserialize_depends<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depends {
+        List(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match Depends::deserialize(deserializer)? {
+        Depends::List(list) => list,
+        Depends::Joined(joined) => joined
+            .split(',')
+            .filter(|d| !d.is_empty())
+            .map(|d| d.to_string())
+            .collect(),
+    })
+}
+
+/// Taskwarrior's compact ISO 8601 dates, `20230102T100000Z`
+mod date {
+    use super::*;
+
+    pub fn serialize<S>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_str(&date.format(DATE_FMT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let Some(value) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        NaiveDateTime::parse_from_str(&value, DATE_FMT)
+            .map(|date| Utc.from_utc_datetime(&date))
+            .or_else(|_| DateTime::parse_from_rfc3339(&value).map(|date| date.into()))
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid date '{}'", value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXPORT: &str = r#"[
+{"id":1,"description":"Write report","entry":"20230101T090000Z","modified":"20230102T100000Z","project":"work.reports","start":"20230102T100000Z","status":"pending","uuid":"5e0a7b4c-3b4e-4a8a-9c27-1d0f2e3a4b5c","depends":["a1b2c3d4-e5f6-4a7b-8c9d-0e1f2a3b4c5d"],"tags":["office"],"priority":"H","urgency":12.3},
+{"id":0,"description":"Collect numbers","end":"20230101T120000Z","entry":"20230101T080000Z","modified":"20230101T120000Z","status":"completed","uuid":"a1b2c3d4-e5f6-4a7b-8c9d-0e1f2a3b4c5d","annotations":[{"entry":"20230101T110000Z","description":"from finance"}]},
+{"id":0,"description":"Gone","entry":"20230101T080000Z","status":"deleted","uuid":"00000000-0000-4000-8000-000000000001"}
+]"#;
+
+    #[test]
+    fn import_export() {
+        assert!(detect(EXPORT));
+        let tasks = import(EXPORT).unwrap();
+        assert_eq!(tasks.len(), 2);
+
+        let report = &tasks[0];
+        assert_eq!(report.name, "Write report");
+        assert_eq!(report.projects, vec!["work.reports".to_string()]);
+        assert_eq!(report.priority, Some('A'));
+        assert!(report.is_started() && !report.is_finished());
+        assert!(report.has_subtask(tasks[1].get_uuid()));
+
+        let numbers = &tasks[1];
+        assert_eq!(numbers.description, "from finance");
+        assert_eq!(
+            numbers.get_finished(),
+            Some(Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap())
+        );
+
+        let exported = export(&tasks.iter().collect::<Vec<&Task>>());
+        assert!(detect(&exported));
+        let reimported = import(&exported).unwrap();
+        assert_eq!(tasks, reimported);
+    }
+
+    #[test]
+    fn json_lines_and_legacy_depends() {
+        let input = "{\"uuid\":\"5e0a7b4c-3b4e-4a8a-9c27-1d0f2e3a4b5c\",\"description\":\"a\",\"status\":\"pending\",\"depends\":\"a1b2c3d4-e5f6-4a7b-8c9d-0e1f2a3b4c5d\"}\n\
+                     {\"uuid\":\"a1b2c3d4-e5f6-4a7b-8c9d-0e1f2a3b4c5d\",\"description\":\"b\",\"status\":\"pending\"}\n";
+        let tasks = import(input).unwrap();
+        assert!(tasks[0].has_subtask(tasks[1].get_uuid()));
+    }
+
+    #[test]
+    fn export_only_running_as_started() {
+        let mut paused = Task::default();
+        paused.start();
+        paused.pause();
+        let mut running = Task::default();
+        running.start();
+        let exported = export(&[&paused, &running]);
+        let tw_tasks = parse(&exported).unwrap();
+        assert_eq!(tw_tasks[0].start, None);
+        assert!(tw_tasks[1].start.is_some());
+    }
+
+    #[test]
+    fn invalid_uuid_line() {
+        let input = "{\"uuid\":\"5e0a7b4c-3b4e-4a8a-9c27-1d0f2e3a4b5c\",\"description\":\"a\",\"status\":\"pending\"}\n\
+                     {\"uuid\":\"not-a-uuid\",\"description\":\"b\",\"status\":\"pending\"}\n";
+        let err = import(input).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn detect_taskman() {
+        let task = Task::default();
+        let taskman = serde_json::to_string(&vec![&task]).unwrap();
+        assert!(!detect(&taskman));
+    }
+}
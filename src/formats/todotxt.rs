@@ -0,0 +1,186 @@
+//! Import and export of the [todo.txt](https://github.com/todotxt/todo.txt) format
+//!
+//! Besides the standard priority, completion mark, dates, `+project` and `@context` tags, the
+//! `due:YYYY-MM-DD` and `pri:X` extensions are understood. The task id is written as `uuid:...`
+//! so a file can be imported again without duplicating tasks. Descriptions and subtasks have no
+//! todo.txt representation and are not exported.
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use uuid::Uuid;
+
+use super::ParseError;
+use crate::task::Task;
+
+const DATE_FMT: &str = "%Y-%m-%d";
+
+pub fn export(tasks: &[&Task]) -> String {
+    let mut out = String::new();
+    for task in tasks {
+        let mut words: Vec<String> = Vec::new();
+        if let Some(finished) = task.get_finished() {
+            words.push("x".to_string());
+            words.push(format_date(finished));
+            words.push(format_date(task.get_creation_time()));
+        } else {
+            if let Some(priority) = task.priority {
+                words.push(format!("({})", priority));
+            }
+            words.push(format_date(task.get_creation_time()));
+        }
+
+        words.extend(task.name.split_whitespace().map(|w| w.to_string()));
+        words.extend(task.projects.iter().map(|p| format!("+{}", p)));
+        words.extend(task.tags.iter().map(|t| format!("@{}", t)));
+        if let Some(due) = task.due {
+            words.push(format!("due:{}", format_date(due)));
+        }
+        if let (Some(priority), true) = (task.priority, task.is_finished()) {
+            // Completed tasks lose their leading priority, keep it as a key instead
+            words.push(format!("pri:{}", priority));
+        }
+        words.push(format!("uuid:{}", task.get_uuid()));
+
+        out.push_str(&words.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn import(input: &str) -> Result<Vec<Task>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| parse_line(line).map_err(|msg| ParseError::new(idx + 1, msg)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<Task, String> {
+    let mut words = line.split_whitespace().peekable();
+    let mut task = Task::default();
+
+    let completed = words.next_if_eq(&"x").is_some();
+    let mut finished = None;
+    if completed {
+        finished = words.next_if(|w| is_date(w)).map(parse_date).transpose()?;
+    } else {
+        task.priority = words
+            .next_if(|w| is_priority(w))
+            .and_then(|w| w.chars().nth(1));
+    }
+    if let Some(created) = words.next_if(|w| is_date(w)).map(parse_date).transpose()? {
+        task.creation_time = created;
+    }
+
+    let mut name: Vec<&str> = Vec::new();
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            task.projects.push(project.to_string());
+        } else if let Some(tag) = word.strip_prefix('@').filter(|t| !t.is_empty()) {
+            task.tags.push(tag.to_string());
+        } else if let Some(("due", value)) = word.split_once(':') {
+            task.due = Some(parse_date(value)?);
+        } else if let Some(("pri", value)) = word.split_once(':') {
+            task.priority = Some(parse_priority(value)?);
+        } else if let Some(("uuid", value)) = word.split_once(':') {
+            task.id = Uuid::parse_str(value).map_err(|err| format!("invalid uuid: {}", err))?;
+        } else {
+            name.push(word);
+        }
+    }
+    task.name = name.join(" ");
+
+    if completed {
+        // Completion date is optional, a task without one is still done
+        task.set_times(None, Some(finished.unwrap_or(task.creation_time)));
+    }
+    Ok(task)
+}
+
+fn is_date(word: &str) -> bool {
+    NaiveDate::parse_from_str(word, DATE_FMT).is_ok()
+}
+
+fn is_priority(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')'
+}
+
+fn parse_priority(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Ok(c),
+        _ => Err(format!("invalid priority '{}'", value)),
+    }
+}
+
+/// Dates are interpreted as local midnight
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDate::parse_from_str(value, DATE_FMT)
+        .ok()
+        .and_then(|date| {
+            Local
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .earliest()
+        })
+        .map(|date| date.with_timezone(&Utc))
+        .ok_or_else(|| format!("invalid date '{}'", value))
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    DateTime::<Local>::from(date).format(DATE_FMT).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn import_lines() {
+        let input = "(A) 2023-01-02 Call mom +Family @phone due:2023-01-10\n\
+                     \n\
+                     x 2023-01-05 2023-01-01 pri:B Fix bike +Garage\n\
+                     x Plain done task\n";
+        let tasks = import(input).unwrap();
+        assert_eq!(tasks.len(), 3);
+
+        assert_eq!(tasks[0].name, "Call mom");
+        assert_eq!(tasks[0].priority, Some('A'));
+        assert_eq!(tasks[0].projects, vec!["Family".to_string()]);
+        assert_eq!(tasks[0].tags, vec!["phone".to_string()]);
+        assert_eq!(tasks[0].due, Some(parse_date("2023-01-10").unwrap()));
+        assert_eq!(
+            tasks[0].get_creation_time(),
+            parse_date("2023-01-02").unwrap()
+        );
+        assert!(!tasks[0].is_started());
+
+        assert_eq!(tasks[1].name, "Fix bike");
+        assert_eq!(tasks[1].priority, Some('B'));
+        assert_eq!(tasks[1].get_finished(), parse_date("2023-01-05").ok());
+        assert_eq!(
+            tasks[1].get_creation_time(),
+            parse_date("2023-01-01").unwrap()
+        );
+
+        assert_eq!(tasks[2].name, "Plain done task");
+        assert!(tasks[2].is_finished());
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "(C) 2023-01-02 Water plants @home due:2023-01-03\n\
+                     x 2023-01-05 2023-01-01 Fix bike +Garage pri:B\n";
+        let tasks = import(input).unwrap();
+        let exported = export(&tasks.iter().collect::<Vec<&Task>>());
+        let reimported = import(&exported).unwrap();
+        assert_eq!(tasks, reimported);
+        assert!(exported.starts_with("(C) 2023-01-02 Water plants @home due:2023-01-03 uuid:"));
+    }
+
+    #[test]
+    fn import_errors() {
+        let err = import("Fine task\nBroken due:tomorrow\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,375 @@
+//! Comparison of imported tasks with the local task list before they are merged into it
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Local, Utc};
+use uuid::Uuid;
+
+use crate::collection::TaskCollection;
+use crate::task::Task;
+
+/// How an imported task relates to the local task list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportKind {
+    New,
+    Identical,
+    Conflict(Vec<FieldDiff>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub local: String,
+    pub imported: String,
+}
+
+/// What to do with an imported task whose id already exists locally
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    KeepLocal,
+    TakeImported,
+    /// Adds the imported task under a new id next to the local one
+    KeepBoth,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [
+        Resolution::KeepLocal,
+        Resolution::TakeImported,
+        Resolution::KeepBoth,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Resolution::KeepLocal => "Keep local",
+            Resolution::TakeImported => "Take imported",
+            Resolution::KeepBoth => "Keep both",
+        }
+    }
+}
+
+pub struct ImportItem {
+    pub task: Task,
+    pub kind: ImportKind,
+    pub resolution: Resolution,
+}
+
+pub struct ImportPreview {
+    pub source: String,
+    pub items: Vec<ImportItem>,
+    /// Names of imported tasks that had the id of an earlier imported task and got a new one
+    pub reassigned: Vec<String>,
+}
+
+/// Names of the tasks affected by an applied import
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    pub duplicated: Vec<String>,
+    pub kept: Vec<String>,
+    pub identical: usize,
+}
+
+impl ImportPreview {
+    pub fn new(
+        source: String,
+        imported: Vec<Task>,
+        local: &HashMap<Uuid, Task>,
+        resolution: Resolution,
+    ) -> Self {
+        let mut seen = HashSet::new();
+        let mut reassigned = Vec::new();
+        let items = imported
+            .into_iter()
+            .map(|mut task| {
+                // Otherwise the later task would silently replace the earlier one
+                if !seen.insert(task.get_uuid()) {
+                    task.id = Uuid::new_v4();
+                    reassigned.push(task.name.clone());
+                }
+                let kind = match local.get(&task.get_uuid()) {
+                    None => ImportKind::New,
+                    Some(existing) if existing == &task => ImportKind::Identical,
+                    Some(existing) => ImportKind::Conflict(diff(existing, &task)),
+                };
+                ImportItem {
+                    task,
+                    kind,
+                    resolution,
+                }
+            })
+            .collect();
+        Self {
+            source,
+            items,
+            reassigned,
+        }
+    }
+
+    pub fn count(&self, kind: fn(&ImportKind) -> bool) -> usize {
+        self.items.iter().filter(|item| kind(&item.kind)).count()
+    }
+
+    pub fn set_all(&mut self, resolution: Resolution) {
+        for item in self.items.iter_mut() {
+            item.resolution = resolution;
+        }
+    }
+
+    pub fn apply(self, local: &mut TaskCollection) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        let mut touched: Vec<Uuid> = Vec::new();
+
+        for item in self.items {
+            match (item.kind, item.resolution) {
+                (ImportKind::New, _) => {
+                    summary.added.push(item.task.name.clone());
+                    touched.push(item.task.get_uuid());
+                    local.insert(item.task);
+                }
+                (ImportKind::Identical, _) => summary.identical += 1,
+                (ImportKind::Conflict(_), Resolution::KeepLocal) => {
+                    summary.kept.push(item.task.name);
+                }
+                (ImportKind::Conflict(_), Resolution::TakeImported) => {
+                    summary.replaced.push(item.task.name.clone());
+                    touched.push(item.task.get_uuid());
+                    local.insert(item.task);
+                }
+                (ImportKind::Conflict(_), Resolution::KeepBoth) => {
+                    let copy = Task {
+                        id: Uuid::new_v4(),
+                        name: format!("{} (imported)", item.task.name),
+                        ..item.task
+                    };
+                    summary.duplicated.push(copy.name.clone());
+                    touched.push(copy.get_uuid());
+                    local.insert(copy);
+                }
+            }
+        }
+
+        // Imported tasks may link to subtasks that exist neither locally nor in the import, or
+        // to local tasks that already have them as subtasks
+        local.drop_broken_links(touched);
+
+        summary
+    }
+}
+
+fn diff(local: &Task, imported: &Task) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+    let mut compare = |field: &'static str, local: String, imported: String| {
+        if local != imported {
+            diffs.push(FieldDiff {
+                field,
+                local,
+                imported,
+            });
+        }
+    };
+
+    compare("Name", local.name.clone(), imported.name.clone());
+    compare(
+        "Description",
+        local.description.clone(),
+        imported.description.clone(),
+    );
+    compare(
+        "Created",
+        format_time(Some(local.get_creation_time())),
+        format_time(Some(imported.get_creation_time())),
+    );
+    compare(
+        "Started",
+        format_time(local.get_started()),
+        format_time(imported.get_started()),
+    );
+    compare(
+        "Finished",
+        format_time(local.get_finished()),
+        format_time(imported.get_finished()),
+    );
+    compare("Due", format_time(local.due), format_time(imported.due));
+    compare(
+        "Priority",
+        local.priority.map(String::from).unwrap_or_default(),
+        imported.priority.map(String::from).unwrap_or_default(),
+    );
+    compare(
+        "Projects",
+        local.projects.join(", "),
+        imported.projects.join(", "),
+    );
+    compare("Tags", local.tags.join(", "), imported.tags.join(", "));
+    compare(
+        "Tracked time",
+        format_intervals(local),
+        format_intervals(imported),
+    );
+    compare(
+        "Subtasks",
+        format_subtasks(local),
+        format_subtasks(imported),
+    );
+    diffs
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| {
+        DateTime::<Local>::from(t)
+            .format("%d.%m.%Y %H:%M:%S")
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+fn format_intervals(task: &Task) -> String {
+    let tracked = task.tracked_time();
+    format!(
+        "{:02}:{:02}:{:02} in {} intervals",
+        tracked.num_hours(),
+        tracked.num_minutes() % 60,
+        tracked.num_seconds() % 60,
+        task.get_intervals().len()
+    )
+}
+
+fn format_subtasks(task: &Task) -> String {
+    task.get_subtasks()
+        .into_iter()
+        .flatten()
+        .map(|(_, name)| name.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn local_tasks() -> (TaskCollection, Task, Task) {
+        let same = Task {
+            name: "Same".to_string(),
+            ..Default::default()
+        };
+        let changed = Task {
+            name: "Changed".to_string(),
+            ..Default::default()
+        };
+        let local = [same.clone(), changed.clone()].into_iter().collect();
+        (local, same, changed)
+    }
+
+    #[test]
+    fn classify_and_apply() {
+        let (mut local, same, changed) = local_tasks();
+        let mut conflicting = changed.clone();
+        conflicting.description = "New description".to_string();
+        let mut new = Task {
+            name: "New".to_string(),
+            ..Default::default()
+        };
+        new.add_subtask(Uuid::new_v4(), "Missing".to_string())
+            .unwrap();
+
+        let preview = ImportPreview::new(
+            "test".to_string(),
+            vec![same, conflicting.clone(), new.clone()],
+            &local,
+            Resolution::TakeImported,
+        );
+        assert_eq!(preview.count(|k| matches!(k, ImportKind::Identical)), 1);
+        assert_eq!(preview.count(|k| matches!(k, ImportKind::New)), 1);
+        assert_eq!(
+            preview.items[1].kind,
+            ImportKind::Conflict(vec![FieldDiff {
+                field: "Description",
+                local: "".to_string(),
+                imported: "New description".to_string(),
+            }])
+        );
+
+        let summary = preview.apply(&mut local);
+        assert_eq!(summary.added, vec!["New".to_string()]);
+        assert_eq!(summary.replaced, vec!["Changed".to_string()]);
+        assert_eq!(summary.identical, 1);
+        assert_eq!(local[&conflicting.get_uuid()], conflicting);
+        // Links to tasks that exist nowhere are dropped
+        assert!(local[&new.get_uuid()].get_subtasks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let (local, same, _) = local_tasks();
+        let twin = Task {
+            name: "Twin".to_string(),
+            ..same.clone()
+        };
+
+        let preview = ImportPreview::new(
+            "test".to_string(),
+            vec![same.clone(), twin],
+            &local,
+            Resolution::TakeImported,
+        );
+        assert_eq!(preview.reassigned, vec!["Twin".to_string()]);
+        assert_eq!(preview.items[0].kind, ImportKind::Identical);
+        assert_eq!(preview.items[1].kind, ImportKind::New);
+        assert_ne!(preview.items[1].task.get_uuid(), same.get_uuid());
+    }
+
+    #[test]
+    fn keep_local_and_both() {
+        let (mut local, _, changed) = local_tasks();
+        let mut conflicting = changed.clone();
+        conflicting.name = "Renamed".to_string();
+
+        let mut preview = ImportPreview::new(
+            "test".to_string(),
+            vec![conflicting.clone()],
+            &local,
+            Resolution::KeepLocal,
+        );
+        let summary = ImportPreview::new(
+            "test".to_string(),
+            vec![conflicting],
+            &local,
+            Resolution::KeepLocal,
+        )
+        .apply(&mut local);
+        assert_eq!(summary.kept.len(), 1);
+        assert_eq!(local[&changed.get_uuid()], changed);
+
+        preview.set_all(Resolution::KeepBoth);
+        let summary = preview.apply(&mut local);
+        assert_eq!(summary.duplicated, vec!["Renamed (imported)".to_string()]);
+        assert_eq!(local.len(), 3);
+        assert_eq!(local[&changed.get_uuid()], changed);
+    }
+
+    #[test]
+    fn no_cycles() {
+        let (mut local, same, changed) = local_tasks();
+        local.link(changed.get_uuid(), same.get_uuid()).unwrap();
+        // The import has it the other way round
+        let mut imported = same.clone();
+        imported
+            .add_subtask(changed.get_uuid(), changed.name.clone())
+            .unwrap();
+
+        let preview = ImportPreview::new(
+            "test".to_string(),
+            vec![imported],
+            &local,
+            Resolution::TakeImported,
+        );
+        preview.apply(&mut local);
+        assert!(local[&changed.get_uuid()].has_subtask(same.get_uuid()));
+        assert!(!local[&same.get_uuid()].has_subtask(changed.get_uuid()));
+        assert!(!local
+            .descendants(same.get_uuid())
+            .contains(&same.get_uuid()));
+    }
+}
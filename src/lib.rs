@@ -1,3 +1,19 @@
-pub mod task;
-pub mod pomodoro;
+pub mod clock;
+pub mod collection;
+pub mod command;
+pub mod error;
+pub mod events;
+pub mod formats;
+pub mod import;
+#[cfg(feature = "gui")]
 pub mod manager;
+pub mod merge;
+pub mod notify;
+pub mod pomodoro;
+pub mod schema;
+pub mod stats;
+pub mod storage;
+pub mod task;
+#[cfg(feature = "gui")]
+pub mod view;
+pub mod workspace;
@@ -1,21 +1,19 @@
-use std::collections::HashMap;
-use uuid::Uuid;
-
 use eframe::egui;
-use eframe::egui::Color32;
-
-mod manager;
-mod pomodoro;
-mod task;
 
-use manager::TaskManager;
-use pomodoro::{Pomodoro, PomodoroStatus};
+use taskman::manager::{StoreLocation, TaskManager};
 
 // TODO: Tags
 // TODO: Task Groups
 // TODO: Styling
 
 fn main() {
+    // An optional path to the task store, `.db` files are SQLite databases, others json.
+    // `--eframe` keeps the tasks in eframe's storage instead.
+    let location = match std::env::args_os().nth(1) {
+        Some(arg) if arg == "--eframe" => StoreLocation::Eframe,
+        Some(path) => StoreLocation::Path(path.into()),
+        None => StoreLocation::Default,
+    };
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1080.0, 1920.0)),
         ..Default::default()
@@ -23,6 +21,6 @@ fn main() {
     eframe::run_native(
         TaskManager::APPNAME,
         options,
-        Box::new(|cc| Box::new(TaskManager::new(cc))),
+        Box::new(|cc| Box::new(TaskManager::new(cc, location))),
     )
 }
@@ -1,67 +1,121 @@
-use std::collections::HashMap;
-use uuid::Uuid;
-
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
-use crate::pomodoro::{Pomodoro, PomodoroStatus};
-use crate::task::{Task, TaskStatus};
+use crate::error;
+use crate::formats::Format;
+use crate::import::{ImportKind, ImportPreview, ImportSummary, Resolution};
+use crate::notify::{DesktopNotifier, NoNotifier, Notifier, NotifierKind, TerminalBell, Toasts};
+use crate::pomodoro::{format_minutes, Phase, Pomodoro, PomodoroSettings, PomodoroStatus, Preset};
+use crate::schema::Document;
+use crate::stats::{self, Totals};
+use crate::storage::eframe::EframeStore;
+use crate::storage::lock;
+use crate::storage::{self, Backup, DataFile};
+use crate::task::{format_duration, Task};
+use crate::view;
+use crate::workspace::Workspace;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, Utc};
 
 use egui::Color32;
 
 use eframe::{self, egui};
 
-enum NotifyStatus {
-    SentBreak,
-    SentWork,
-    Nothing,
+/// Where the task list is kept
+pub enum StoreLocation {
+    /// The SQLite database in the data directory
+    Default,
+    /// A json file or SQLite database at the given path
+    Path(PathBuf),
+    /// eframe's own storage, next to the app's settings
+    Eframe,
 }
 
 pub struct TaskManager {
-    tasks: HashMap<Uuid, Task>,
+    workspace: Workspace,
 
     show_creation_dialog: bool,
     tmp_task: Option<Task>,
 
     err_msg: Option<String>,
 
-    edit: Option<Uuid>,
-
-    notified: NotifyStatus,
-    pomodoro: Option<Pomodoro>,
-    pomo_work: u32,
-    pomo_break: u32,
+    /// Task picked for the next pomodoro
+    pomo_task: Option<Uuid>,
+    show_stats: bool,
+    /// Name the current settings are saved as
+    preset_name: String,
+    show_notify_settings: bool,
+    toasts: Toasts,
     squash_import: bool,
+    import_preview: Option<ImportPreview>,
+    import_summary: Option<ImportSummary>,
+    export_format: Format,
+    /// Tasks are kept in eframe's storage, which is only available while the app is saved
+    eframe_store: bool,
+    /// Backups with their task count, shown while restoring
+    backups: Option<Vec<(Backup, Result<usize, String>)>>,
+    last_poll: Instant,
+    /// Shown once on start when the store is locked
+    lock_dialog: bool,
+    /// Set to bring the window to the front, `raised` while it is put on top for a frame
+    raise: bool,
+    raised: bool,
+    /// Size of the full window while the mini timer is shown instead
+    mini: Option<egui::Vec2>,
+    /// Set to switch between the mini timer and the full window
+    toggle_mini: bool,
+    /// Only the focused task is shown instead of the task list
+    focus: bool,
+    /// The task chosen for focus, else the active one is shown
+    focus_task: Option<Uuid>,
 }
 
 impl TaskManager {
     pub const APPNAME: &str = "taskman";
     pub const TASK_LIST: &str = "task_list";
+    /// File name of the task store in the data directory
+    pub const STORE: &str = "tasks.db";
+    pub const MAX_BACKUPS: &str = "max_backups";
+    pub const PERSIST_HISTORY: &str = "persist_history";
+    /// How often the store file is checked for changes made elsewhere
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const MINI_SIZE: egui::Vec2 = egui::vec2(320.0, 130.0);
+    /// Key of the window settings eframe persists
+    const WINDOW: &str = "window";
     const CLR_PUSHED: egui::Color32 = egui::Color32::DARK_GREEN;
     const CLR_NORMAL: egui::Color32 = egui::Color32::DARK_GRAY;
 
     const CLR_CONFIRM: egui::Color32 = egui::Color32::DARK_GREEN;
     const CLR_ABORT: egui::Color32 = egui::Color32::DARK_RED;
-
-    const CLR_DONE: egui::Color32 = egui::Color32::DARK_GREEN;
-    const CLR_INPROGRESS: egui::Color32 = egui::Color32::from_rgb_additive(0x89, 0x38, 0x01);
-    const CLR_NOTSTARTED: egui::Color32 = egui::Color32::DARK_GRAY;
 }
 
 impl Default for TaskManager {
     fn default() -> Self {
         Self {
-            tasks: HashMap::new(),
+            workspace: Workspace::default(),
             show_creation_dialog: false,
             tmp_task: None,
-            edit: None,
-            pomodoro: None,
-            pomo_work: 25,
-            pomo_break: 5,
-            notified: NotifyStatus::Nothing,
+            pomo_task: None,
+            show_stats: false,
+            preset_name: String::new(),
+            show_notify_settings: false,
+            toasts: Toasts::default(),
             err_msg: None,
             squash_import: false,
+            import_preview: None,
+            import_summary: None,
+            export_format: Format::Json,
+            eframe_store: false,
+            backups: None,
+            last_poll: Instant::now(),
+            lock_dialog: false,
+            raise: false,
+            raised: false,
+            mini: None,
+            toggle_mini: false,
+            focus: false,
+            focus_task: None,
         }
     }
 }
@@ -95,30 +149,137 @@ fn configure_text_styles(ctx: &egui::Context) {
 }
 
 impl TaskManager {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        configure_text_styles(&&cc.egui_ctx);
+    pub fn new(cc: &eframe::CreationContext<'_>, location: StoreLocation) -> Self {
+        configure_text_styles(&cc.egui_ctx);
 
+        let data_dir = storage::data_dir(TaskManager::APPNAME);
+        let is_default = matches!(location, StoreLocation::Default);
         let mut tm = Self::default();
+        match location {
+            StoreLocation::Default => match &data_dir {
+                Some(dir) => tm.workspace = Workspace::open(&dir.join(TaskManager::STORE)),
+                None => tm
+                    .workspace
+                    .fail_load("No data directory found, changes will not be saved".to_string()),
+            },
+            StoreLocation::Path(path) => tm.workspace = Workspace::open(&path),
+            StoreLocation::Eframe => tm.eframe_store = true,
+        }
+        tm.lock_dialog = tm.workspace.is_read_only();
         if let Some(storage) = cc.storage {
-            println!("Found Storage");
-            if let Some(res) = storage.get_string(&TaskManager::TASK_LIST) {
-                println!("Found task list entry");
-                if let Ok(mut conv) = serde_json::from_str::<Vec<Task>>(&res) {
-                    for task in conv.drain(..) {
-                        tm.tasks.insert(task.get_uuid(), task);
-                    }
-                    println!("Got task list of size {}", tm.tasks.len());
-                    // TODO: Verify task link integrity
-                }
+            if let Some(max) = storage
+                .get_string(TaskManager::MAX_BACKUPS)
+                .and_then(|max| max.parse().ok())
+            {
+                tm.workspace.max_backups = max;
             }
+            tm.workspace.persist_history =
+                storage.get_string(TaskManager::PERSIST_HISTORY).as_deref() == Some("true");
+        }
+
+        // Task lists used to be kept in a json file and before that in eframe's storage
+        let legacy = || {
+            data_dir
+                .filter(|_| is_default)
+                .and_then(|dir| DataFile::new(&dir.join("tasks.json")).read().ok().flatten())
+                .or_else(|| {
+                    cc.storage
+                        .filter(|_| is_default)
+                        .and_then(|storage| storage.get_string(TaskManager::TASK_LIST))
+                })
+        };
+        if tm.eframe_store {
+            let stored = cc.storage.map(|storage| {
+                (
+                    EframeStore::read(storage, TaskManager::TASK_LIST),
+                    "eframe storage".to_string(),
+                )
+            });
+            tm.workspace.load_stored(stored, legacy);
         } else {
-            panic!("No storage found")
+            tm.workspace.load(legacy);
         }
+        tm.take_errors();
         tm
     }
 
+    /// Shows failures of the workspace and the conflicts it found while merging
+    fn take_errors(&mut self) {
+        if let Some(err) = self.workspace.take_error() {
+            self.err_msg = Some(err);
+        }
+        if let Some(conflicts) = self.workspace.take_conflicts() {
+            self.import_preview = Some(conflicts);
+        }
+    }
+
+    /// Asks the window holding the lock to come to the front and closes this one
+    fn switch_to_holder(&mut self, frame: &mut eframe::Frame) {
+        let Some(path) = self.workspace.store().and_then(|store| store.file()) else {
+            return;
+        };
+        match lock::request_focus(path) {
+            Ok(()) => frame.close(),
+            Err(err) => {
+                self.err_msg = Some(format!("Could not reach the other window: {}", err));
+            }
+        }
+    }
+
+    fn lock_dialog(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if !self.lock_dialog {
+            return;
+        }
+        let location = self
+            .workspace
+            .store()
+            .map(|store| store.location())
+            .unwrap_or_default();
+        let holder = self
+            .workspace
+            .store()
+            .and_then(|store| store.file())
+            .and_then(lock::holder)
+            .map(|pid| format!(" (process {})", pid))
+            .unwrap_or_default();
+        let mut switch = false;
+        egui::Window::new("Task List in Use")
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "'{}' is open in another {} window{}. Changes made here would be lost, so it is opened read-only until the other window is closed.",
+                    location,
+                    TaskManager::APPNAME,
+                    holder
+                ));
+                ui.columns(2, |cols| {
+                    if cols[0].button("Switch to Other Window").clicked() {
+                        switch = true;
+                    }
+                    if cols[1].button("Open Read-Only").clicked() {
+                        self.lock_dialog = false;
+                    }
+                });
+            });
+        if switch {
+            self.switch_to_holder(frame);
+        }
+    }
+
+    /// Merges changes another program or window made to the store file and comes to the front
+    /// when another window asks for it
+    fn check_external(&mut self) {
+        self.last_poll = Instant::now();
+        self.workspace.check_external();
+        if self.workspace.focus_requested() {
+            self.raise = true;
+        }
+    }
+
     fn add_task(&mut self, task: Task) {
-        self.tasks.insert(task.get_uuid(), task);
+        self.workspace.execute("Create task", |tasks| {
+            tasks.insert(task);
+        });
     }
 
     fn creation_dialog(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
@@ -130,21 +291,19 @@ impl TaskManager {
                 .resizable(true)
                 .show(ctx, |ui| {
                     for event in ui.input().events.clone() {
-                        match event {
-                            egui::Event::Key {
-                                key,
-                                pressed,
-                                modifiers,
-                            } => {
-                                if key == egui::Key::W && pressed && modifiers.ctrl {
-                                    self.show_creation_dialog = false;
-                                }
-                                if key == egui::Key::Enter && pressed && modifiers.ctrl {
-                                    defer_add = true;
-                                    self.show_creation_dialog = false;
-                                }
+                        if let egui::Event::Key {
+                            key,
+                            pressed,
+                            modifiers,
+                        } = event
+                        {
+                            if key == egui::Key::W && pressed && modifiers.ctrl {
+                                self.show_creation_dialog = false;
+                            }
+                            if key == egui::Key::Enter && pressed && modifiers.ctrl {
+                                defer_add = true;
+                                self.show_creation_dialog = false;
                             }
-                            _ => (),
                         }
                     }
 
@@ -172,7 +331,7 @@ impl TaskManager {
                             let heading = egui::RichText::new("Select subtasks")
                                 .text_style(egui::TextStyle::Name("Heading3".into()));
                             ui.label(heading);
-                            for existing_task in self.tasks.values() {
+                            for existing_task in self.workspace.tasks().values() {
                                 let mut selected = new_task.has_subtask(existing_task.get_uuid());
                                 let before = selected;
 
@@ -186,13 +345,16 @@ impl TaskManager {
                                     selected = !selected;
                                 };
                                 if before != selected {
-                                    if selected {
+                                    let result = if selected {
                                         new_task.add_subtask(
                                             existing_task.get_uuid(),
                                             existing_task.name.clone(),
-                                        );
+                                        )
                                     } else {
-                                        new_task.remove_subtask(existing_task.get_uuid());
+                                        new_task.remove_subtask(existing_task.get_uuid())
+                                    };
+                                    if let Err(err) = result {
+                                        self.err_msg = Some(err.to_string());
                                     }
                                 }
                             }
@@ -218,57 +380,104 @@ impl TaskManager {
                 });
         }
 
-        if defer_add {
-            let task = self.tmp_task.take().unwrap();
+        if let (true, Some(task)) = (defer_add, self.tmp_task.take()) {
             self.add_task(task);
         }
     }
 
     fn edit_pane(&mut self, ctx: &egui::Context) {
         let mut defer_delete = false;
+        let mut close = false;
+        let mut commit = false;
+        let mut toggle: Option<(Uuid, bool)> = None;
 
-        if let Some(uuid) = &self.edit {
-            let mut task_names: Vec<(Uuid, String, DateTime<Utc>)> = self
-                .tasks
-                .values()
-                .map(|x| (x.get_uuid(), x.name.clone(), x.get_creation_time()))
+        if let Some(uuid) = self.workspace.edited() {
+            // Tasks that could be linked without creating a cycle
+            let task_names: Vec<(Uuid, String, bool)> = self
+                .workspace
+                .tasks()
+                .sorted()
+                .into_iter()
+                .filter(|task| task.get_uuid() != uuid)
+                .map(|task| {
+                    let id = task.get_uuid();
+                    (
+                        id,
+                        task.name.clone(),
+                        self.workspace.tasks().can_link(uuid, id),
+                    )
+                })
                 .collect();
+            let preset_names: Vec<String> = self
+                .workspace
+                .pomodoro_presets
+                .iter()
+                .chain(&Preset::builtin())
+                .map(|preset| preset.name.clone())
+                .collect();
+            let history: Vec<String> = self
+                .workspace
+                .edit_events()
+                .iter()
+                .rev()
+                .map(|event| {
+                    format!(
+                        "{} {}: {}",
+                        DateTime::<Local>::from(event.time).format("%d.%m.%Y %H:%M:%S"),
+                        event.author,
+                        event.describe()
+                    )
+                })
+                .collect();
+            let read_only = self.workspace.is_read_only();
 
-            task_names.sort_by_key(|(_id, _name, time)| *time);
-
-            let task_names = task_names
-                .drain(..)
-                .map(|(id, name, _)| (id, name))
-                .collect::<Vec<(Uuid, String)>>();
-
-            let edit_task = self.tasks.get_mut(&uuid).unwrap();
+            let Some(edit_task) = self.workspace.edited_mut() else {
+                return;
+            };
             egui::SidePanel::right("Edit Task")
                 .resizable(true)
                 .show_animated(ctx, true, |ui| {
+                    ui.set_enabled(!read_only);
                     egui::Grid::new("Grid").striped(false).show(ui, |ui| {
                         if ui
                             .add(egui::Button::new("x").fill(Color32::DARK_RED))
                             .clicked()
                         {
-                            self.edit = None;
+                            close = true;
                         }
                         ui.add_sized([ui.available_width(), 0.0], egui::Label::new("Task Name"));
                     });
 
-                    ui.add_sized(
-                        [ui.available_width(), 0.0],
-                        egui::TextEdit::singleline(&mut edit_task.name)
-                            .font(egui::TextStyle::Name("Heading2".into())),
-                    );
+                    commit |= ui
+                        .add_sized(
+                            [ui.available_width(), 0.0],
+                            egui::TextEdit::singleline(&mut edit_task.name)
+                                .font(egui::TextStyle::Name("Heading2".into())),
+                        )
+                        .lost_focus();
                     ui.separator();
 
                     let description_label = ui.label("Description");
-                    ui.add_sized(
-                        [ui.available_width(), 0.0],
-                        egui::TextEdit::multiline(&mut edit_task.description)
-                            .font(egui::TextStyle::Body),
-                    )
-                    .labelled_by(description_label.id);
+                    commit |= ui
+                        .add_sized(
+                            [ui.available_width(), 0.0],
+                            egui::TextEdit::multiline(&mut edit_task.description)
+                                .font(egui::TextStyle::Body),
+                        )
+                        .labelled_by(description_label.id)
+                        .lost_focus();
+
+                    let preset = &mut edit_task.pomodoro_preset;
+                    egui::ComboBox::from_label("Pomodoro Preset")
+                        .selected_text(preset.as_deref().unwrap_or("None"))
+                        .show_ui(ui, |ui| {
+                            commit |= ui.selectable_value(preset, None, "None").clicked();
+                            for name in &preset_names {
+                                commit |= ui
+                                    .selectable_value(preset, Some(name.clone()), name)
+                                    .clicked();
+                            }
+                        });
 
                     ui.separator();
                     egui::ScrollArea::new([false, true]).show(ui, |ui| {
@@ -276,60 +485,65 @@ impl TaskManager {
                             let heading = egui::RichText::new("Select subtasks")
                                 .text_style(egui::TextStyle::Name("Heading3".into()));
                             ui.label(heading);
-                            for (eid, ename) in task_names.iter() {
-                                if *eid == edit_task.get_uuid() {
-                                    continue;
-                                }
-                                let mut selected = edit_task.has_subtask(*eid);
-                                let before = selected;
-
+                            for (eid, ename, linkable) in task_names.iter() {
+                                let selected = edit_task.has_subtask(*eid);
                                 if ui
-                                    .add_sized(
-                                        [ui.available_width(), 0.0],
-                                        egui::SelectableLabel::new(selected, ename.clone()),
-                                    )
+                                    .add_enabled_ui(selected || *linkable, |ui| {
+                                        ui.add_sized(
+                                            [ui.available_width(), 0.0],
+                                            egui::SelectableLabel::new(selected, ename.clone()),
+                                        )
+                                    })
+                                    .inner
                                     .clicked()
                                 {
-                                    selected = !selected;
-                                };
-                                if before != selected {
-                                    if selected {
-                                        edit_task.add_subtask(*eid, ename.clone());
-                                    } else {
-                                        edit_task.remove_subtask(*eid);
-                                    }
+                                    toggle = Some((*eid, !selected));
                                 }
                             }
                         });
                     });
 
+                    ui.separator();
+                    ui.collapsing("History", |ui| {
+                        for line in &history {
+                            ui.label(line);
+                        }
+                    });
+
                     if ui.button("Delete").clicked() {
                         defer_delete = true;
                     }
                     // TODO: reset start and finish times
                 });
         }
-        if defer_delete {
-            let to_del = self.edit.take().unwrap();
-            for task in self.tasks.values_mut() {
-                if task.has_subtask(to_del) {
-                    task.remove_subtask(to_del);
-                }
-            }
-            self.tasks.remove(&to_del);
+        if commit {
+            self.workspace.commit_edit();
+        }
+        if let (Some(id), Some((sub, selected))) = (self.workspace.edited(), toggle) {
+            let result = if selected {
+                self.workspace
+                    .execute("Add subtask", |tasks| tasks.link(id, sub))
+            } else {
+                self.workspace
+                    .execute("Remove subtask", |tasks| tasks.unlink(id, sub))
+            };
+            self.report(result);
+        }
+        if let (true, Some(to_del)) = (defer_delete, self.workspace.edited()) {
+            self.workspace
+                .execute("Delete task", |tasks| tasks.delete(to_del));
+        }
+        if close {
+            self.workspace.open_edit(None);
         }
     }
 
     fn task_list(&mut self, ui: &mut egui::Ui) {
-        let mut stati: HashMap<Uuid, TaskStatus> = self
-            .tasks
-            .values()
-            .map(|v| (v.get_uuid(), v.status()))
-            .collect();
-
-        let mut tasks: Vec<&mut Task> = self.tasks.values_mut().collect();
-        tasks.sort_by_key(|x| x.get_creation_time());
-        for task in tasks.iter_mut().rev() {
+        let mut defer_start = None;
+        let mut defer_finish = None;
+        let mut defer_edit = None;
+
+        for task in self.workspace.tasks().sorted().into_iter().rev() {
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
                     if ui
@@ -340,7 +554,7 @@ impl TaskManager {
                         }))
                         .clicked()
                     {
-                        task.start();
+                        defer_start = Some(task.get_uuid());
                     }
 
                     if ui
@@ -351,118 +565,421 @@ impl TaskManager {
                         }))
                         .clicked()
                     {
-                        task.finish();
+                        defer_finish = Some(task.get_uuid());
                     }
                 });
 
                 let sep = egui::Separator::default();
                 ui.add(sep);
                 ui.vertical(|ui| {
-                    if task.display(ui) {
-                        self.edit = Some(task.get_uuid())
+                    if view::task_summary(task, ui) {
+                        defer_edit = Some(task.get_uuid())
                     };
                     ui.vertical(|ui| {
-                        if let Some(subtasks) = task.get_subtasks() {
-                            for (id, name) in subtasks.iter() {
-                                let label =
-                                    egui::RichText::new(name).color(match stati.get(id).unwrap() {
-                                        TaskStatus::NotYet => TaskManager::CLR_NOTSTARTED,
-                                        TaskStatus::Started => TaskManager::CLR_INPROGRESS,
-                                        TaskStatus::Finished => TaskManager::CLR_DONE,
-                                    });
-                                ui.label(label);
-                            }
+                        for subtask in self.workspace.tasks().subtasks(task.get_uuid()) {
+                            ui.label(
+                                egui::RichText::new(&subtask.name)
+                                    .color(view::status_color(&subtask.status())),
+                            );
                         }
                     });
                 });
             });
             ui.separator();
         }
+
+        if let Some(id) = defer_start {
+            let result = self.workspace.start_task(id);
+            self.report(result);
+        }
+        if let Some(id) = defer_finish {
+            let result = self.workspace.finish_task(id);
+            self.report(result);
+        }
+        if defer_edit.is_some() {
+            self.workspace.open_edit(defer_edit);
+        }
     }
 
-    fn pomodoro_display(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+    fn pomodoro_display(&mut self, ui: &mut egui::Ui) {
         ui.heading("Pomodoro");
-        if ui.button("Start / Stop").clicked() {
-            match self.pomodoro {
-                Some(_) => self.pomodoro = None,
-                None => {
-                    self.pomodoro = Some(Pomodoro::new(
-                        chrono::Duration::minutes(self.pomo_work.into()),
-                        chrono::Duration::minutes(self.pomo_break.into()),
-                    ))
-                }
+        ui.columns(2, |cols| {
+            if cols[0].button("Start / Stop").clicked() {
+                self.workspace.toggle_pomodoro(self.pomo_task);
             }
-        }
+            if cols[1].button("Mini Timer").clicked() {
+                self.toggle_mini = true;
+            }
+        });
 
-        if let Some(pomo) = &self.pomodoro {
-            // Request Repaint so that progress bar updates regularly
-            ctx.request_repaint();
+        let (mut pause, mut skip, mut start_next) = (false, false, false);
+        if let Some(pomo) = self.workspace.pomodoro() {
+            ui.columns(2, |cols| match pomo.status() {
+                PomodoroStatus::Running(phase, _) => {
+                    let label = if pomo.is_paused() { "Resume" } else { "Pause" };
+                    pause = cols[0].button(label).clicked();
+                    let label = match phase {
+                        Phase::Work => "Skip to Break",
+                        Phase::ShortBreak | Phase::LongBreak => "End Break",
+                    };
+                    skip = cols[1].button(label).clicked();
+                }
+                PomodoroStatus::Waiting(phase) => {
+                    start_next = cols[0].button(format!("Start {}", phase.name())).clicked();
+                }
+                PomodoroStatus::Done => (),
+            });
+        }
+        if pause {
+            self.workspace.toggle_pause();
+        }
+        if skip {
+            self.workspace.skip_phase();
+        }
+        if start_next {
+            self.workspace.start_next_phase();
+        }
 
-            // Handle Notification
-            match pomo.status() {
-                PomodoroStatus::Work(_) => match self.notified {
-                    NotifyStatus::SentBreak | NotifyStatus::Nothing => {
-                        notify_rust::Notification::new()
-                            .summary("Start Working")
-                            .body(&format!(
-                                "Working interval time: {}",
-                                display_duration_min_s(pomo.work_time)
-                            ))
-                            .show()
-                            .unwrap();
-                        self.notified = NotifyStatus::SentWork;
+        if let Some(pomo) = self.workspace.pomodoro() {
+            if let Some(task) = pomo.task.and_then(|id| self.workspace.tasks().get(&id)) {
+                ui.label(format!("Working on {}", task.name));
+            }
+            ui.label(format!(
+                "Cycle {} of {}",
+                pomo.cycle(),
+                pomo.settings.cycles
+            ));
+            ui.add(pomodoro_bar(pomo));
+            if pomo.is_paused() {
+                ui.label("Paused");
+            }
+        } else {
+            let selected = self
+                .pomo_task
+                .and_then(|id| self.workspace.tasks().get(&id))
+                .map_or("No Task", |task| task.name.as_str());
+            egui::ComboBox::from_label("Task")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.pomo_task, None, "No Task");
+                    for task in self.workspace.tasks().sorted() {
+                        if !task.is_finished() {
+                            ui.selectable_value(
+                                &mut self.pomo_task,
+                                Some(task.get_uuid()),
+                                &task.name,
+                            );
+                        }
                     }
-                    _ => (),
-                },
-                PomodoroStatus::Break(_) => match self.notified {
-                    NotifyStatus::SentWork | NotifyStatus::Nothing => {
-                        notify_rust::Notification::new()
-                            .summary("Take a Break")
-                            .body(&format!(
-                                "Break interval time: {}",
-                                display_duration_min_s(pomo.break_time)
-                            ))
-                            .show()
-                            .unwrap();
-                        self.notified = NotifyStatus::SentBreak;
+                });
+
+            egui::ComboBox::from_label("Preset")
+                .selected_text("Load Preset")
+                .show_ui(ui, |ui| {
+                    for preset in self
+                        .workspace
+                        .pomodoro_presets
+                        .iter()
+                        .chain(&Preset::builtin())
+                    {
+                        if ui.selectable_label(false, &preset.name).clicked() {
+                            self.workspace.pomodoro_settings = preset.settings.clone();
+                            self.preset_name = preset.name.clone();
+                        }
                     }
-                    _ => (),
-                },
-                PomodoroStatus::Done => match self.notified {
-                    NotifyStatus::SentBreak | NotifyStatus::SentWork => {
-                        notify_rust::Notification::new()
-                            .summary("Pomodoro is Done")
-                            .show()
-                            .unwrap();
-                        self.notified = NotifyStatus::Nothing;
+                });
+            ui.columns(2, |cols| {
+                cols[0].text_edit_singleline(&mut self.preset_name);
+                let name = self.preset_name.trim();
+                if cols[1]
+                    .add_enabled(!name.is_empty(), egui::Button::new("Save Preset"))
+                    .clicked()
+                {
+                    let preset = Preset {
+                        name: name.to_string(),
+                        settings: self.workspace.pomodoro_settings.clone(),
+                    };
+                    match self
+                        .workspace
+                        .pomodoro_presets
+                        .iter_mut()
+                        .find(|p| p.name == preset.name)
+                    {
+                        Some(existing) => *existing = preset,
+                        None => self.workspace.pomodoro_presets.push(preset),
                     }
-                    _ => (),
-                },
+                }
+            });
+
+            let settings = &mut self.workspace.pomodoro_settings;
+            duration_edit(ui, &mut settings.work_time, "Work Interval");
+            duration_edit(ui, &mut settings.short_break, "Break Interval");
+            duration_edit(ui, &mut settings.long_break, "Long Break Interval");
+            ui.add(egui::Slider::new(&mut settings.cycles, 1..=10))
+                .labelled_by(ui.label("Cycles").id);
+            ui.checkbox(
+                &mut settings.auto_start,
+                "Start next interval automatically",
+            );
+        }
+        ui.columns(2, |cols| {
+            if cols[0].button("Statistics").clicked() {
+                self.show_stats = true;
             }
+            if cols[1].button("Notifications").clicked() {
+                self.show_notify_settings = true;
+            }
+        });
+    }
 
-            let pbar = match pomo.status() {
-                PomodoroStatus::Work(work_time_elapsed) => egui::ProgressBar::new(
-                    work_time_elapsed.num_seconds() as f32 / pomo.work_time.num_seconds() as f32,
-                )
-                .text(format!(
-                    "Work Time: {}",
-                    display_duration_min_s(work_time_elapsed)
-                )),
-                PomodoroStatus::Break(break_time_elapsed) => egui::ProgressBar::new(
-                    break_time_elapsed.num_seconds() as f32 / pomo.break_time.num_seconds() as f32,
+    fn poll_pomodoro(&mut self, ctx: &egui::Context) {
+        if self.workspace.pomodoro().is_none() {
+            return;
+        }
+        // Request Repaint so that progress bar updates regularly
+        ctx.request_repaint();
+        let notifier: &mut dyn Notifier = match self.workspace.notify_settings.kind {
+            NotifierKind::Desktop => &mut DesktopNotifier,
+            NotifierKind::Toast => &mut self.toasts,
+            NotifierKind::Bell => &mut TerminalBell,
+            NotifierKind::Off => &mut NoNotifier,
+        };
+        self.workspace.poll_pomodoro(notifier);
+    }
+
+    /// The task worked on: the one of the pomodoro, else a running or the linked one
+    fn active_task(&self) -> Option<Uuid> {
+        self.workspace.active_task(self.pomo_task)
+    }
+
+    /// The compact view of the active task and the pomodoro
+    fn mini_timer(&mut self, ui: &mut egui::Ui) {
+        let active = self.active_task();
+        match active.and_then(|id| self.workspace.tasks().get(&id)) {
+            Some(task) => {
+                ui.horizontal(|ui| {
+                    ui.strong(&task.name);
+                    ui.label(format_duration(task.tracked_time()));
+                });
+            }
+            None => {
+                ui.label("No Task");
+            }
+        }
+        if let Some(pomo) = self.workspace.pomodoro() {
+            ui.add(pomodoro_bar(pomo));
+        }
+
+        let state = self
+            .workspace
+            .pomodoro()
+            .map(|pomo| (pomo.status(), pomo.is_paused()));
+        let play_label = match state {
+            None => "Start".to_string(),
+            Some((PomodoroStatus::Running(..), true)) => "Resume".to_string(),
+            Some((PomodoroStatus::Running(..), false)) => "Pause".to_string(),
+            Some((PomodoroStatus::Waiting(phase), _)) => format!("Start {}", phase.name()),
+            Some((PomodoroStatus::Done, _)) => "Stop".to_string(),
+        };
+        let (mut play, mut done) = (false, false);
+        ui.horizontal(|ui| {
+            play = ui.button(play_label).clicked();
+            done = ui
+                .add_enabled(
+                    active.is_some() && !self.workspace.is_read_only(),
+                    egui::Button::new("Done"),
                 )
-                .text(format!(
-                    "Break Time: {}",
-                    display_duration_min_s(break_time_elapsed)
-                )),
-                PomodoroStatus::Done => egui::ProgressBar::new(1.0).text("Done"),
-            };
-            ui.add(pbar);
-        } else {
-            ui.add(egui::Slider::new(&mut self.pomo_work, 1..=60))
-                .labelled_by(ui.label("Work Interval").id);
-            ui.add(egui::Slider::new(&mut self.pomo_break, 1..=60))
-                .labelled_by(ui.label("Break Interval").id);
+                .clicked();
+            if ui.button("Full Window").clicked() {
+                self.toggle_mini = true;
+            }
+        });
+
+        if play {
+            match state {
+                None => {
+                    self.pomo_task = active;
+                    self.workspace.toggle_pomodoro(self.pomo_task);
+                }
+                Some((PomodoroStatus::Running(..), _)) => self.workspace.toggle_pause(),
+                Some((PomodoroStatus::Waiting(_), _)) => self.workspace.start_next_phase(),
+                Some((PomodoroStatus::Done, _)) => self.workspace.toggle_pomodoro(self.pomo_task),
+            }
+        }
+        if let (true, Some(id)) = (done, active) {
+            if self
+                .workspace
+                .pomodoro()
+                .is_some_and(|pomo| pomo.task == Some(id))
+            {
+                self.workspace.toggle_pomodoro(self.pomo_task);
+            }
+            // The linked task may not be started yet, finishing starts it first
+            let result = self.workspace.finish_task(id);
+            self.report(result);
+        }
+    }
+
+    /// The focused task with its subtasks as a checklist, and the pomodoro
+    fn focus_view(&mut self, ui: &mut egui::Ui) {
+        let focused = self.focus_task.or_else(|| self.active_task());
+        ui.horizontal(|ui| {
+            let selected = self
+                .focus_task
+                .and_then(|id| self.workspace.tasks().get(&id))
+                .map_or("Current Task", |task| task.name.as_str());
+            egui::ComboBox::from_label("Focus on")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.focus_task, None, "Current Task");
+                    for task in self.workspace.tasks().sorted() {
+                        if !task.is_finished() {
+                            ui.selectable_value(
+                                &mut self.focus_task,
+                                Some(task.get_uuid()),
+                                &task.name,
+                            );
+                        }
+                    }
+                });
+            if ui.button("Leave Focus").clicked() {
+                self.focus = false;
+            }
+        });
+        ui.separator();
+
+        let mut check = None;
+        match focused.and_then(|id| self.workspace.tasks().get(&id)) {
+            Some(task) => {
+                ui.heading(&task.name);
+                ui.label(format!("Tracked {}", format_duration(task.tracked_time())));
+                if !task.description.is_empty() {
+                    ui.label(&task.description);
+                }
+                ui.add_space(8.0);
+                for subtask in self.workspace.tasks().subtasks(task.get_uuid()) {
+                    // Finished subtasks stay checked, there is no way to unfinish a task
+                    let mut done = subtask.is_finished();
+                    let enabled = !done && !self.workspace.is_read_only();
+                    if ui
+                        .add_enabled(enabled, egui::Checkbox::new(&mut done, &subtask.name))
+                        .changed()
+                    {
+                        check = Some(subtask.get_uuid());
+                    }
+                }
+            }
+            None => {
+                ui.label("No task is started, choose one to focus on");
+            }
+        }
+        if let Some(id) = check {
+            let result = self.workspace.finish_task(id);
+            self.report(result);
+        }
+
+        ui.separator();
+        self.pomodoro_display(ui);
+    }
+
+    /// Switches between the mini timer on top of other windows and the full window
+    fn toggle_mini(&mut self, frame: &mut eframe::Frame) {
+        self.toggle_mini = false;
+        match self.mini.take() {
+            Some(size) => {
+                frame.set_window_size(size);
+                frame.set_always_on_top(false);
+            }
+            None => {
+                self.mini = Some(frame.info().window_info.size);
+                frame.set_window_size(TaskManager::MINI_SIZE);
+                frame.set_always_on_top(true);
+            }
+        }
+    }
+
+    /// Buttons in the left panel changing the task list
+    fn task_actions(&mut self, ui: &mut egui::Ui) {
+        ui.columns(2, |cols| {
+            if cols[0].button("New Task").clicked() {
+                self.show_creation_dialog = true;
+            }
+            if cols[1].button("Export").clicked() {
+                self.export();
+            }
+        });
+        egui::ComboBox::from_label("Export Format")
+            .selected_text(self.export_format.name())
+            .show_ui(ui, |ui| {
+                for format in Format::ALL {
+                    ui.selectable_value(&mut self.export_format, format, format.name());
+                }
+            });
+
+        ui.separator();
+        ui.columns(2, |cols| {
+            if cols[0].button("Import").clicked() {
+                self.import();
+            }
+            if cols[1]
+                .selectable_label(self.squash_import, "Squash Existing on Import")
+                .clicked()
+            {
+                self.squash_import = !self.squash_import;
+            }
+        });
+
+        ui.separator();
+        ui.columns(2, |cols| {
+            let undo = self
+                .workspace
+                .history()
+                .undo_label()
+                .map(|l| format!("Undo {}", l));
+            if cols[0]
+                .add_enabled(undo.is_some(), egui::Button::new("Undo"))
+                .on_hover_text(undo.unwrap_or_default())
+                .clicked()
+            {
+                self.workspace.undo();
+            }
+            let redo = self
+                .workspace
+                .history()
+                .redo_label()
+                .map(|l| format!("Redo {}", l));
+            if cols[1]
+                .add_enabled(redo.is_some(), egui::Button::new("Redo"))
+                .on_hover_text(redo.unwrap_or_default())
+                .clicked()
+            {
+                self.workspace.redo();
+            }
+        });
+        ui.checkbox(
+            &mut self.workspace.persist_history,
+            "Keep undo history after restart",
+        );
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.workspace.max_backups).clamp_range(1..=100));
+            ui.label("Backups kept");
+        });
+        ui.columns(2, |cols| {
+            if cols[0].button("Restore from Backup").clicked() {
+                self.open_backups();
+            }
+            if cols[1].button("Rebuild from Log").clicked() {
+                self.workspace.rebuild_from_log();
+            }
+        });
+    }
+
+    /// Shows a failed operation in the error window
+    fn report(&mut self, result: error::Result<()>) {
+        if let Err(err) = result {
+            self.err_msg = Some(err.to_string());
         }
     }
 
@@ -481,33 +998,361 @@ impl TaskManager {
         }
     }
 
+    fn import_preview_win(&mut self, ctx: &egui::Context) {
+        let mut apply = false;
+        let mut cancel = false;
+        if let Some(preview) = &mut self.import_preview {
+            egui::Window::new("Import Preview")
+                .collapsible(false)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    ui.label(format!("Importing from {}", preview.source));
+                    ui.label(format!(
+                        "{} new, {} identical, {} conflicting",
+                        preview.count(|k| matches!(k, ImportKind::New)),
+                        preview.count(|k| matches!(k, ImportKind::Identical)),
+                        preview.count(|k| matches!(k, ImportKind::Conflict(_))),
+                    ));
+                    if !preview.reassigned.is_empty() {
+                        ui.colored_label(
+                            view::CLR_INPROGRESS,
+                            format!(
+                                "Imported under a new id, the file has another task with the same id: {}",
+                                preview.reassigned.join(", ")
+                            ),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("All conflicts:");
+                        for resolution in Resolution::ALL {
+                            if ui.button(resolution.label()).clicked() {
+                                preview.set_all(resolution);
+                            }
+                        }
+                    });
+                    ui.separator();
+
+                    egui::ScrollArea::vertical()
+                        .max_height(ui.available_height() - 40.0)
+                        .show(ui, |ui| {
+                            for (idx, item) in preview.items.iter_mut().enumerate() {
+                                match &item.kind {
+                                    ImportKind::New => {
+                                        ui.label(
+                                            egui::RichText::new(format!("New: {}", item.task.name))
+                                                .color(TaskManager::CLR_CONFIRM),
+                                        );
+                                    }
+                                    ImportKind::Identical => {
+                                        ui.label(
+                                            egui::RichText::new(format!(
+                                                "Identical: {}",
+                                                item.task.name
+                                            ))
+                                            .color(view::CLR_NOTSTARTED),
+                                        );
+                                    }
+                                    ImportKind::Conflict(diffs) => {
+                                        ui.label(
+                                            egui::RichText::new(format!(
+                                                "Conflict: {}",
+                                                item.task.name
+                                            ))
+                                            .color(view::CLR_INPROGRESS),
+                                        );
+                                        egui::Grid::new(("import diff", idx)).striped(true).show(
+                                            ui,
+                                            |ui| {
+                                                ui.strong("Field");
+                                                ui.strong("Local");
+                                                ui.strong("Imported");
+                                                ui.end_row();
+                                                for diff in diffs {
+                                                    ui.label(diff.field);
+                                                    ui.label(&diff.local);
+                                                    ui.label(&diff.imported);
+                                                    ui.end_row();
+                                                }
+                                            },
+                                        );
+                                        ui.horizontal(|ui| {
+                                            for resolution in Resolution::ALL {
+                                                ui.radio_value(
+                                                    &mut item.resolution,
+                                                    resolution,
+                                                    resolution.label(),
+                                                );
+                                            }
+                                        });
+                                        ui.separator();
+                                    }
+                                }
+                            }
+                        });
+
+                    ui.separator();
+                    ui.columns(2, |cols| {
+                        if cols[0]
+                            .add(egui::Button::new("Import").fill(TaskManager::CLR_CONFIRM))
+                            .clicked()
+                        {
+                            apply = true;
+                        }
+                        if cols[1]
+                            .add(egui::Button::new("Cancel").fill(TaskManager::CLR_ABORT))
+                            .clicked()
+                        {
+                            cancel = true;
+                        }
+                    });
+                });
+        }
+
+        if let (true, Some(preview)) = (apply, self.import_preview.take()) {
+            self.import_summary = Some(
+                self.workspace
+                    .execute("Import", |tasks| preview.apply(tasks)),
+            );
+        }
+        if cancel {
+            self.import_preview = None;
+        }
+    }
+
+    fn import_summary_win(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+        if let Some(summary) = &self.import_summary {
+            egui::Window::new("Import Summary").show(ctx, |ui| {
+                for (label, names) in [
+                    ("Added", &summary.added),
+                    ("Replaced", &summary.replaced),
+                    ("Added as copy", &summary.duplicated),
+                    ("Kept local", &summary.kept),
+                ] {
+                    if !names.is_empty() {
+                        ui.collapsing(format!("{} ({})", label, names.len()), |ui| {
+                            for name in names {
+                                ui.label(name);
+                            }
+                        });
+                    }
+                }
+                ui.label(format!("Unchanged: {}", summary.identical));
+                if ui.button("Ok").clicked() {
+                    close = true;
+                }
+            });
+        }
+        if close {
+            self.import_summary = None;
+        }
+    }
+
+    fn open_backups(&mut self) {
+        let Some(backups) = self.workspace.backups() else {
+            self.err_msg = Some("This task store keeps no backups".to_string());
+            return;
+        };
+        match backups.list() {
+            Ok(backups) => {
+                self.backups = Some(
+                    backups
+                        .into_iter()
+                        .map(|backup| {
+                            let count = backup
+                                .read()
+                                .map_err(|err| err.to_string())
+                                .and_then(|input| {
+                                    Document::load(&input).map_err(|err| err.to_string())
+                                })
+                                .map(|doc| doc.tasks.len());
+                            (backup, count)
+                        })
+                        .collect(),
+                )
+            }
+            Err(err) => self.err_msg = Some(format!("Could not list backups: {}", err)),
+        }
+    }
+
+    fn backups_win(&mut self, ctx: &egui::Context) {
+        let mut restore = None;
+        let mut close = false;
+        if let Some(backups) = &self.backups {
+            egui::Window::new("Restore from Backup")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    if backups.is_empty() {
+                        ui.label("No backups yet");
+                    }
+                    egui::Grid::new("backups").striped(true).show(ui, |ui| {
+                        for (backup, count) in backups {
+                            ui.label(
+                                DateTime::<Local>::from(backup.time)
+                                    .format("%d.%m.%Y %H:%M:%S")
+                                    .to_string(),
+                            );
+                            match count {
+                                Ok(count) => {
+                                    ui.label(format!("{} tasks", count));
+                                    if ui.button("Restore").clicked() {
+                                        restore = Some(backup.clone());
+                                    }
+                                }
+                                Err(err) => {
+                                    ui.label(format!("Unreadable: {}", err));
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+        }
+
+        if let Some(backup) = restore {
+            self.workspace.restore(&backup);
+            close = true;
+        }
+        if close {
+            self.backups = None;
+        }
+    }
+
+    fn stats_win(&mut self, ctx: &egui::Context) {
+        if !self.show_stats {
+            return;
+        }
+        let days = stats::daily(self.workspace.sessions(), &Local);
+        let weeks = stats::weekly(self.workspace.sessions(), &Local);
+        let today = Local::now().date_naive();
+        let row = |ui: &mut egui::Ui, label: String, totals: Option<&Totals>| {
+            let totals = totals.copied().unwrap_or_default();
+            ui.label(label);
+            ui.label(totals.pomodoros.to_string());
+            ui.label(format_duration(totals.focus));
+            ui.end_row();
+        };
+        egui::Window::new("Pomodoro Statistics")
+            .open(&mut self.show_stats)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("stats_days").striped(true).show(ui, |ui| {
+                    ui.strong("Day");
+                    ui.strong("Pomodoros");
+                    ui.strong("Focus Time");
+                    ui.end_row();
+                    for ago in 0..7 {
+                        let day = today - chrono::Duration::days(ago);
+                        row(ui, day.format("%a %d.%m.").to_string(), days.get(&day));
+                    }
+                });
+                ui.separator();
+                egui::Grid::new("stats_weeks").striped(true).show(ui, |ui| {
+                    ui.strong("Week");
+                    ui.strong("Pomodoros");
+                    ui.strong("Focus Time");
+                    ui.end_row();
+                    for (monday, totals) in weeks.iter().rev().take(4) {
+                        row(ui, monday.format("%d.%m.%Y").to_string(), Some(totals));
+                    }
+                });
+            });
+    }
+
+    fn notify_settings_win(&mut self, ctx: &egui::Context) {
+        let settings = &mut self.workspace.notify_settings;
+        egui::Window::new("Notifications")
+            .open(&mut self.show_notify_settings)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Show as")
+                    .selected_text(settings.kind.name())
+                    .show_ui(ui, |ui| {
+                        for kind in NotifierKind::ALL {
+                            ui.selectable_value(&mut settings.kind, kind, kind.name());
+                        }
+                    });
+                ui.label(
+                    egui::RichText::new(
+                        "{task}, {phase}, {duration}, {cycle} and {cycles} are replaced",
+                    )
+                    .text_style(egui::TextStyle::Name("Smaller".into())),
+                );
+                egui::Grid::new("notify_templates").show(ui, |ui| {
+                    for (name, template) in settings.templates_mut() {
+                        ui.checkbox(&mut template.enabled, name);
+                        ui.add_enabled(
+                            template.enabled,
+                            egui::TextEdit::singleline(&mut template.summary),
+                        );
+                        ui.add_enabled(
+                            template.enabled,
+                            egui::TextEdit::singleline(&mut template.body),
+                        );
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// Messages of the in-app notifier, in the bottom right corner
+    fn toasts(&mut self, ctx: &egui::Context) {
+        let mut shown = false;
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .show(ctx, |ui| {
+                for message in self.toasts.current(Utc::now()) {
+                    shown = true;
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.strong(&message.summary);
+                        ui.label(&message.body);
+                    });
+                }
+            });
+        // Keep repainting so toasts disappear on time
+        if shown {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
     fn import(&mut self) {
-        let maybe_path = rfd::FileDialog::new()
-            .set_directory(home::home_dir().unwrap_or(".".into()))
-            .add_filter("json", &["json"])
+        let maybe_path = Format::ALL
+            .iter()
+            .fold(
+                rfd::FileDialog::new().set_directory(home::home_dir().unwrap_or(".".into())),
+                |dialog, format| dialog.add_filter(format.name(), format.extensions()),
+            )
             .pick_file();
 
         if let Some(path) = maybe_path {
-            match std::fs::File::open(&path) {
-                Ok(infile) => {
-                    println!("Importing from {}.", path.to_str().unwrap());
-                    match serde_json::from_reader::<_, Vec<Task>>(infile) {
-                        Ok(mut tasks) => {
-                            for task in &mut tasks.drain(..) {
+            match std::fs::read_to_string(&path) {
+                Ok(input) => {
+                    let Some(format) = Format::detect(&path, &input) else {
+                        self.err_msg = Some(format!("Unknown file format of '{}'", path.display()));
+                        return;
+                    };
+                    println!("Importing {} from {}.", format.name(), path.display());
+                    match format.parse(&input) {
+                        Ok(tasks) => {
+                            self.import_preview = Some(ImportPreview::new(
+                                path.display().to_string(),
+                                tasks,
+                                self.workspace.tasks(),
                                 if self.squash_import {
-                                    self.add_task(task);
+                                    Resolution::TakeImported
                                 } else {
-                                    if !self.tasks.contains_key(&task.get_uuid()) {
-                                        self.add_task(task);
-                                    }
-                                }
-                            }
+                                    Resolution::KeepLocal
+                                },
+                            ));
                         }
                         Err(err) => {
                             self.err_msg = Some(format!(
                                 "Error during parsing of file '{}': {}",
-                                path.to_str().unwrap(),
-                                err.to_string()
+                                path.display(),
+                                err
                             ))
                         }
                     }
@@ -518,49 +1363,117 @@ impl TaskManager {
     }
 
     fn export(&mut self) {
+        let format = self.export_format;
         let maybe_path = rfd::FileDialog::new()
             .set_directory(home::home_dir().unwrap_or(".".into()))
-            .add_filter("json", &["json"])
+            .add_filter(format.name(), format.extensions())
             .save_file();
 
-        if let Some(path) = maybe_path {
-            match std::fs::File::create(&path) {
-                Ok(outfile) => {
-                    println!("Saving to {}.", path.to_str().unwrap());
-                    serde_json::to_writer(
-                        outfile,
-                        &self.tasks.iter().map(|(_, x)| x).collect::<Vec<&Task>>(),
-                    )
-                    .unwrap();
-                }
-                Err(err) => self.err_msg = Some(err.to_string()),
+        if let Some(mut path) = maybe_path {
+            if path.extension().is_none() {
+                path.set_extension(format.extensions()[0]);
+            }
+            let tasks = self.workspace.tasks().sorted();
+
+            println!("Saving {} to {}.", format.name(), path.display());
+            if let Err(err) = storage::write_atomic(&path, &format.write(&tasks)) {
+                self.err_msg = Some(format!("Could not export to '{}': {}", path.display(), err));
             }
         }
     }
 }
 
-fn display_duration_min_s(d: chrono::Duration) -> String {
-    format!(
-        "{}:{:02}",
-        d.num_minutes(),
-        d.num_seconds() - d.num_minutes() * 60
-    )
+fn pomodoro_bar(pomo: &Pomodoro) -> egui::ProgressBar {
+    match pomo.status() {
+        PomodoroStatus::Running(phase, elapsed) => egui::ProgressBar::new(
+            elapsed.num_seconds() as f32 / pomo.settings.duration(phase).num_seconds() as f32,
+        )
+        .text(format!(
+            "{} Time: {}",
+            phase.name(),
+            format_minutes(elapsed)
+        )),
+        PomodoroStatus::Waiting(phase) => {
+            egui::ProgressBar::new(0.0).text(format!("{} is Next", phase.name()))
+        }
+        PomodoroStatus::Done => egui::ProgressBar::new(1.0).text("Done"),
+    }
+}
+
+/// Hours, minutes and seconds of `duration`, kept within what a phase can last
+fn duration_edit(ui: &mut egui::Ui, duration: &mut chrono::Duration, label: &str) {
+    let total = duration.num_seconds();
+    let (mut hours, mut minutes, mut seconds) = (total / 3600, total / 60 % 60, total % 60);
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(
+            egui::DragValue::new(&mut hours)
+                .clamp_range(0..=PomodoroSettings::MAX_HOURS)
+                .suffix("h"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut minutes)
+                .clamp_range(0..=59)
+                .suffix("m"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut seconds)
+                .clamp_range(0..=59)
+                .suffix("s"),
+        );
+    });
+    *duration = PomodoroSettings::phase_duration(hours, minutes, seconds);
+}
+
+/// The window settings eframe persists before [`eframe::App::save`]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WindowSettings {
+    position: Option<egui::Pos2>,
+    fullscreen: bool,
+    inner_size_points: Option<egui::Vec2>,
 }
 
 impl eframe::App for TaskManager {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if self.last_poll.elapsed() >= TaskManager::POLL_INTERVAL {
+            self.check_external();
+        }
+        ctx.request_repaint_after(TaskManager::POLL_INTERVAL);
+        // Raising the window is not supported directly, putting it on top for a frame does it
+        if self.raise {
+            frame.set_visible(true);
+            frame.set_always_on_top(true);
+            (self.raise, self.raised) = (false, true);
+            ctx.request_repaint();
+        } else if self.raised {
+            frame.set_always_on_top(self.mini.is_some());
+            self.raised = false;
+        }
+
         for event in ctx.input().events.clone() {
-            match event {
-                egui::Event::Key {
-                    key,
-                    pressed,
-                    modifiers,
-                } => {
-                    if key == egui::Key::N && pressed && modifiers.ctrl {
-                        self.show_creation_dialog = true;
+            if let egui::Event::Key {
+                key,
+                pressed,
+                modifiers,
+            } = event
+            {
+                if key == egui::Key::M && pressed && modifiers.ctrl {
+                    self.toggle_mini = true;
+                }
+                if self.workspace.is_read_only() {
+                    continue;
+                }
+                if key == egui::Key::N && pressed && modifiers.ctrl {
+                    self.show_creation_dialog = true;
+                }
+                // Focused text fields have their own undo
+                if key == egui::Key::Z && pressed && modifiers.ctrl && !ctx.wants_keyboard_input() {
+                    if modifiers.shift {
+                        self.workspace.redo();
+                    } else {
+                        self.workspace.undo();
                     }
                 }
-                _ => (),
             }
         }
 
@@ -573,52 +1486,91 @@ impl eframe::App for TaskManager {
             }
         }
 
-        egui::SidePanel::left("Left Side").show(ctx, |ui| {
-            ui.heading("Tasks");
-            ui.columns(2, |cols| {
-                if cols[0].button("New Task").clicked() {
-                    self.show_creation_dialog = true;
-                }
-                if cols[1].button("Export").clicked() {
-                    self.export();
-                }
-            });
+        self.poll_pomodoro(ctx);
 
-            ui.separator();
-            ui.columns(2, |cols| {
-                if cols[0].button("Import").clicked() {
-                    self.import();
-                }
-                if cols[1]
-                    .selectable_label(self.squash_import, "Squash Existing on Import")
-                    .clicked()
-                {
-                    self.squash_import = !self.squash_import;
-                }
+        if self.mini.is_some() {
+            // The running time of the task is shown in seconds
+            ctx.request_repaint_after(Duration::from_secs(1));
+            egui::CentralPanel::default().show(ctx, |ui| self.mini_timer(ui));
+        } else if self.focus {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| self.focus_view(ui));
             });
+        } else {
+            egui::SidePanel::left("Left Side").show(ctx, |ui| {
+                if self.workspace.is_read_only() {
+                    ui.colored_label(
+                        Color32::DARK_RED,
+                        "Read-only, the task list is open in another window",
+                    );
+                    if ui.button("Switch to Other Window").clicked() {
+                        self.switch_to_holder(frame);
+                    }
+                    ui.separator();
+                }
+                ui.heading("Tasks");
+                ui.add_enabled_ui(!self.workspace.is_read_only(), |ui| self.task_actions(ui));
 
-            ui.separator();
-            self.pomodoro_display(ctx, ui);
-        });
+                ui.separator();
+                self.pomodoro_display(ui);
+            });
 
-        self.edit_pane(ctx);
+            self.edit_pane(ctx);
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.heading("All Tasks");
-                self.task_list(ui);
+            egui::CentralPanel::default().show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("All Tasks");
+                        if ui.button("Focus").clicked() {
+                            self.focus = true;
+                        }
+                    });
+                    ui.add_enabled_ui(!self.workspace.is_read_only(), |ui| self.task_list(ui));
+                });
             });
-        });
+        }
 
+        self.take_errors();
+        self.lock_dialog(ctx, frame);
+        self.import_preview_win(ctx);
+        self.import_summary_win(ctx);
+        self.backups_win(ctx);
+        self.stats_win(ctx);
+        self.notify_settings_win(ctx);
+        self.toasts(ctx);
         self.err_win(ctx);
+
+        if self.toggle_mini {
+            self.toggle_mini(frame);
+            ctx.request_repaint();
+        }
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         storage.set_string(
-            "task_list",
-            serde_json::to_string(&self.tasks.iter().map(|(_, x)| x).collect::<Vec<&Task>>())
-                .unwrap(),
+            TaskManager::MAX_BACKUPS,
+            self.workspace.max_backups.to_string(),
         );
+        storage.set_string(
+            TaskManager::PERSIST_HISTORY,
+            self.workspace.persist_history.to_string(),
+        );
+        // Quitting in mini mode reopens the full window, not the mini timer
+        if let Some(size) = self.mini {
+            if let Some(mut window) =
+                eframe::get_value::<WindowSettings>(storage, TaskManager::WINDOW)
+            {
+                window.inner_size_points = Some(size);
+                eframe::set_value(storage, TaskManager::WINDOW, &window);
+            }
+        }
         storage.flush();
+        if self.eframe_store {
+            self.workspace
+                .save_to(&mut EframeStore::new(storage, TaskManager::TASK_LIST));
+        } else {
+            self.workspace.save();
+        }
+        self.take_errors();
     }
 }
@@ -0,0 +1,118 @@
+//! Merging changes made to the stored task list by someone else into the open one
+//!
+//! Both sides are compared with the task list as it was last loaded or saved: a task changed on
+//! one side only takes that change, a task changed differently on both sides is a conflict.
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::collection::TaskCollection;
+use crate::task::Task;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Merge {
+    /// The open tasks with all changes that do not conflict applied
+    pub tasks: TaskCollection,
+    /// Stored versions of the tasks changed on both sides, the open versions are kept in `tasks`
+    pub conflicts: Vec<Task>,
+}
+
+/// Merges the stored tasks `theirs` into the open tasks `ours`, both changed from `base`
+pub fn three_way(
+    base: &HashMap<Uuid, Task>,
+    ours: &HashMap<Uuid, Task>,
+    theirs: &HashMap<Uuid, Task>,
+) -> Merge {
+    let ids: HashSet<Uuid> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .copied()
+        .collect();
+
+    let mut merge = Merge::default();
+    for id in ids {
+        let (base, ours, theirs) = (base.get(&id), ours.get(&id), theirs.get(&id));
+        let merged = if ours == base || ours == theirs {
+            theirs
+        } else if theirs == base {
+            ours
+        } else {
+            // Deleted there but changed here, the changed task is kept
+            if let Some(theirs) = theirs {
+                merge.conflicts.push(theirs.clone());
+            }
+            ours
+        };
+        if let Some(task) = merged {
+            merge.tasks.insert(task.clone());
+        }
+    }
+    merge
+        .conflicts
+        .sort_by_key(|task| (task.get_creation_time(), task.get_uuid()));
+    merge
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map(tasks: &[&Task]) -> HashMap<Uuid, Task> {
+        tasks
+            .iter()
+            .map(|task| (task.get_uuid(), (*task).clone()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_both_sides() {
+        let unchanged = Task::default();
+        let ours_only = Task::default();
+        let theirs_only = Task::default();
+        let conflicting = Task::default();
+        let deleted_there = Task::default();
+        let base = map(&[
+            &unchanged,
+            &ours_only,
+            &theirs_only,
+            &conflicting,
+            &deleted_there,
+        ]);
+
+        let rename = |task: &Task, name: &str| Task {
+            name: name.to_string(),
+            ..task.clone()
+        };
+        let added_here = Task::default();
+        let ours = map(&[
+            &unchanged,
+            &rename(&ours_only, "Ours"),
+            &theirs_only,
+            &rename(&conflicting, "Ours"),
+            &deleted_there,
+            &added_here,
+        ]);
+        let added_there = Task::default();
+        let theirs_conflict = rename(&conflicting, "Theirs");
+        let theirs = map(&[
+            &unchanged,
+            &ours_only,
+            &rename(&theirs_only, "Theirs"),
+            &theirs_conflict,
+            &added_there,
+        ]);
+
+        let merge = three_way(&base, &ours, &theirs);
+        assert_eq!(merge.conflicts, vec![theirs_conflict]);
+        let name = |task: &Task| merge.tasks[&task.get_uuid()].name.as_str();
+        assert_eq!(name(&ours_only), "Ours");
+        assert_eq!(name(&theirs_only), "Theirs");
+        assert_eq!(name(&conflicting), "Ours");
+        assert!(merge.tasks.contains_key(&added_here.get_uuid()));
+        assert!(merge.tasks.contains_key(&added_there.get_uuid()));
+        assert!(!merge.tasks.contains_key(&deleted_there.get_uuid()));
+        assert_eq!(merge.tasks.len(), 6);
+    }
+}
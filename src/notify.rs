@@ -0,0 +1,259 @@
+//! Notifications about pomodoro phase changes
+//!
+//! Messages are rendered from user-editable templates, where `{task}`, `{phase}`, `{duration}`,
+//! `{cycle}` and `{cycles}` are replaced, and sent through a [`Notifier`].
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+#[cfg(feature = "gui")]
+use crate::error::TaskmanError;
+use crate::pomodoro::{format_minutes, Phase, Pomodoro, PomodoroEvent};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub summary: String,
+    pub body: String,
+}
+
+/// Something that shows messages to the user
+pub trait Notifier {
+    fn notify(&mut self, message: &Message) -> Result<()>;
+}
+
+/// Notifications of the desktop environment
+#[cfg(feature = "gui")]
+pub struct DesktopNotifier;
+
+#[cfg(feature = "gui")]
+impl Notifier for DesktopNotifier {
+    fn notify(&mut self, message: &Message) -> Result<()> {
+        notify_rust::Notification::new()
+            .summary(&message.summary)
+            .body(&message.body)
+            .show()
+            .map(|_| ())
+            .map_err(|err| TaskmanError::Notification(err.to_string()))
+    }
+}
+
+/// Rings the terminal bell and prints the message
+pub struct TerminalBell;
+
+impl Notifier for TerminalBell {
+    fn notify(&mut self, message: &Message) -> Result<()> {
+        let mut out = io::stdout().lock();
+        writeln!(out, "\x07{} {}", message.summary, message.body)?;
+        Ok(out.flush()?)
+    }
+}
+
+/// Drops all messages
+pub struct NoNotifier;
+
+impl Notifier for NoNotifier {
+    fn notify(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps all messages, for tests
+#[derive(Debug, Default)]
+pub struct RecordingNotifier {
+    pub sent: Vec<Message>,
+}
+
+impl Notifier for RecordingNotifier {
+    fn notify(&mut self, message: &Message) -> Result<()> {
+        self.sent.push(message.clone());
+        Ok(())
+    }
+}
+
+/// Messages shown inside the app for a while
+#[derive(Debug, Default)]
+pub struct Toasts {
+    shown: Vec<(Message, DateTime<Utc>)>,
+}
+
+impl Toasts {
+    /// How long a toast stays
+    const SHOW_FOR: i64 = 8;
+
+    /// The messages still to be shown at `now`
+    pub fn current(&mut self, now: DateTime<Utc>) -> impl Iterator<Item = &Message> {
+        self.shown
+            .retain(|(_, at)| now - *at < Duration::seconds(Toasts::SHOW_FOR));
+        self.shown.iter().map(|(message, _)| message)
+    }
+}
+
+impl Notifier for Toasts {
+    fn notify(&mut self, message: &Message) -> Result<()> {
+        self.shown.push((message.clone(), Utc::now()));
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifierKind {
+    Desktop,
+    Toast,
+    Bell,
+    Off,
+}
+
+impl NotifierKind {
+    pub const ALL: [NotifierKind; 4] = [
+        NotifierKind::Desktop,
+        NotifierKind::Toast,
+        NotifierKind::Bell,
+        NotifierKind::Off,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotifierKind::Desktop => "Desktop",
+            NotifierKind::Toast => "In App",
+            NotifierKind::Bell => "Terminal Bell",
+            NotifierKind::Off => "Off",
+        }
+    }
+}
+
+/// Whether and how one kind of event is announced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Template {
+    pub enabled: bool,
+    pub summary: String,
+    pub body: String,
+}
+
+impl Template {
+    fn new(summary: &str, body: &str) -> Self {
+        Self {
+            enabled: true,
+            summary: summary.to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifySettings {
+    pub kind: NotifierKind,
+    pub work: Template,
+    pub short_break: Template,
+    pub long_break: Template,
+    /// The next phase waits to be started
+    pub waiting: Template,
+    pub done: Template,
+}
+
+impl Default for NotifySettings {
+    fn default() -> Self {
+        Self {
+            kind: NotifierKind::Desktop,
+            work: Template::new("Start Working", "Work on {task} for {duration}"),
+            short_break: Template::new("Take a Break", "Break interval time: {duration}"),
+            long_break: Template::new("Take a Long Break", "Break interval time: {duration}"),
+            waiting: Template::new("{phase} is Next", "Start it when you are ready"),
+            done: Template::new("Pomodoro is Done", "{cycles} cycles of {task}"),
+        }
+    }
+}
+
+impl NotifySettings {
+    /// The events with their names, for settings
+    pub fn templates_mut(&mut self) -> [(&'static str, &mut Template); 5] {
+        [
+            ("Work", &mut self.work),
+            ("Break", &mut self.short_break),
+            ("Long Break", &mut self.long_break),
+            ("Waiting", &mut self.waiting),
+            ("Done", &mut self.done),
+        ]
+    }
+
+    fn template(&self, event: PomodoroEvent) -> Option<&Template> {
+        let template = match event {
+            PomodoroEvent::Started(Phase::Work, _) => &self.work,
+            PomodoroEvent::Started(Phase::ShortBreak, _) => &self.short_break,
+            PomodoroEvent::Started(Phase::LongBreak, _) => &self.long_break,
+            PomodoroEvent::Waiting(_) => &self.waiting,
+            PomodoroEvent::Done => &self.done,
+            PomodoroEvent::Completed(..) => return None,
+        };
+        template.enabled.then_some(template)
+    }
+
+    /// The message announcing `event` of `pomo`, `None` if it is not announced
+    pub fn message(
+        &self,
+        event: PomodoroEvent,
+        pomo: &Pomodoro,
+        task: Option<&str>,
+    ) -> Option<Message> {
+        let template = self.template(event)?;
+        let phase = match event {
+            PomodoroEvent::Started(phase, _) | PomodoroEvent::Waiting(phase) => Some(phase),
+            _ => None,
+        };
+        let duration = phase.map_or(Duration::zero(), |phase| pomo.settings.duration(phase));
+        let render = |text: &str| {
+            text.replace("{task}", task.unwrap_or("no task"))
+                .replace("{phase}", phase.map_or("", |phase| phase.name()))
+                .replace("{duration}", &format_minutes(duration))
+                .replace("{cycle}", &pomo.cycle().to_string())
+                .replace("{cycles}", &pomo.settings.cycles.to_string())
+        };
+        Some(Message {
+            summary: render(&template.summary),
+            body: render(&template.body),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::pomodoro::PomodoroSettings;
+
+    #[test]
+    fn render_templates() {
+        let mut settings = NotifySettings::default();
+        settings.short_break.enabled = false;
+        let pomo = Pomodoro::new(PomodoroSettings::default());
+        let mut notifier = RecordingNotifier::default();
+
+        for event in [
+            PomodoroEvent::Started(Phase::Work, pomo.start),
+            PomodoroEvent::Completed(Phase::Work, pomo.start),
+            PomodoroEvent::Started(Phase::ShortBreak, pomo.start),
+            PomodoroEvent::Waiting(Phase::LongBreak),
+        ] {
+            if let Some(message) = settings.message(event, &pomo, Some("Taxes")) {
+                notifier.notify(&message).unwrap();
+            }
+        }
+        assert_eq!(
+            notifier.sent,
+            vec![
+                Message {
+                    summary: "Start Working".to_string(),
+                    body: "Work on Taxes for 25:00".to_string(),
+                },
+                Message {
+                    summary: "Long Break is Next".to_string(),
+                    body: "Start it when you are ready".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -1,40 +1,625 @@
+//! Pomodoro sessions: a number of work phases with short breaks between them and a long break at
+//! the end
+
 use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-pub struct Pomodoro {
-    pub start: DateTime<Utc>,
+use crate::clock::{Clock, SystemClock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Work => "Work",
+            Phase::ShortBreak => "Break",
+            Phase::LongBreak => "Long Break",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PomodoroSettings {
+    #[serde(with = "seconds")]
     pub work_time: Duration,
-    pub break_time: Duration,
+    #[serde(with = "seconds")]
+    pub short_break: Duration,
+    #[serde(with = "seconds")]
+    pub long_break: Duration,
+    /// Work phases in a session, the last one is followed by the long break
+    pub cycles: u32,
+    /// Whether the next phase starts as soon as one ends or waits to be started
+    pub auto_start: bool,
 }
 
+impl PomodoroSettings {
+    /// Longest phase that can be set, in hours
+    pub const MAX_HOURS: i64 = 999;
+
+    /// A phase duration from its parts, kept between one second and [`Self::MAX_HOURS`]
+    pub fn phase_duration(hours: i64, minutes: i64, seconds: i64) -> Duration {
+        let max = Self::MAX_HOURS * 3600;
+        let total = hours
+            .checked_mul(3600)
+            .and_then(|total| total.checked_add(minutes.checked_mul(60)?))
+            .and_then(|total| total.checked_add(seconds))
+            .unwrap_or(if hours < 0 { 1 } else { max });
+        Duration::seconds(total.clamp(1, max))
+    }
+
+    pub fn duration(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => self.work_time,
+            Phase::ShortBreak => self.short_break,
+            Phase::LongBreak => self.long_break,
+        }
+    }
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        Self {
+            work_time: Duration::minutes(25),
+            short_break: Duration::minutes(5),
+            long_break: Duration::minutes(15),
+            cycles: 4,
+            auto_start: true,
+        }
+    }
+}
+
+/// Pomodoro settings saved under a name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub settings: PomodoroSettings,
+}
+
+impl Preset {
+    fn new(name: &str, work: i64, short_break: i64, long_break: i64, cycles: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            settings: PomodoroSettings {
+                work_time: Duration::minutes(work),
+                short_break: Duration::minutes(short_break),
+                long_break: Duration::minutes(long_break),
+                cycles,
+                auto_start: true,
+            },
+        }
+    }
+
+    /// The presets that come with the app
+    pub fn builtin() -> Vec<Preset> {
+        vec![
+            Preset::new("Classic", 25, 5, 15, 4),
+            Preset::new("52/17", 52, 17, 17, 4),
+            Preset::new("90/20", 90, 20, 30, 2),
+        ]
+    }
+
+    /// The settings of the preset called `name`, presets of the user take precedence over the
+    /// built-in ones
+    pub fn find(user: &[Preset], name: &str) -> Option<PomodoroSettings> {
+        user.iter()
+            .cloned()
+            .chain(Preset::builtin())
+            .find(|preset| preset.name == name)
+            .map(|preset| preset.settings)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PomodoroStatus {
-    Work(Duration),
-    Break(Duration),
+    /// The current phase and the time spent in it
+    Running(Phase, Duration),
+    /// The previous phase is over and this one waits to be started
+    Waiting(Phase),
+    Done,
+}
+
+/// A phase as it was run, kept in the session history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Seconds the phase spent paused
+    pub paused: i64,
+    /// Whether the phase ran for its full duration
+    pub completed: bool,
+}
+
+impl PhaseRecord {
+    /// Time spent in the phase without pauses
+    pub fn duration(&self) -> Duration {
+        self.end - self.start - Duration::seconds(self.paused)
+    }
+}
+
+/// A pomodoro session after it is done or stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: Uuid,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub task: Option<Uuid>,
+    pub phases: Vec<PhaseRecord>,
+    /// Whether all phases were run, `false` if the session was stopped before
+    pub completed: bool,
+}
+
+impl Session {
+    /// Work phases run for their full duration
+    pub fn pomodoros(&self) -> u32 {
+        self.phases
+            .iter()
+            .filter(|p| p.phase == Phase::Work && p.completed)
+            .count() as u32
+    }
+
+    /// Time spent in work phases
+    pub fn focus_time(&self) -> Duration {
+        self.phases
+            .iter()
+            .filter(|p| p.phase == Phase::Work)
+            .fold(Duration::zero(), |acc, p| acc + p.duration())
+    }
+}
+
+/// A phase change, reported once by [`Pomodoro::poll`]
+///
+/// Phases caught up with after a restart are reported late, so they carry the time they
+/// actually ended or started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PomodoroEvent {
+    /// The phase ran for its full duration, skipped phases are not completed
+    Completed(Phase, DateTime<Utc>),
+    Started(Phase, DateTime<Utc>),
+    Waiting(Phase),
     Done,
 }
 
+/// A running session, saved with the task list so it continues after a restart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pomodoro {
+    id: Uuid,
+    pub start: DateTime<Utc>,
+    pub settings: PomodoroSettings,
+    /// The task worked on, its time is tracked during work phases
+    pub task: Option<Uuid>,
+    /// Index of the current phase, work phases are the even ones
+    phase: usize,
+    /// When the current phase was started, `None` while it waits to be started
+    phase_start: Option<DateTime<Utc>>,
+    /// Time the current phase spent paused, it does not count towards the phase
+    #[serde(with = "seconds")]
+    paused: Duration,
+    /// When the pomodoro was paused, `None` while it runs
+    paused_at: Option<DateTime<Utc>>,
+    /// Phase changes not yet reported
+    #[serde(skip)]
+    events: Vec<PomodoroEvent>,
+    /// Phases run so far
+    history: Vec<PhaseRecord>,
+}
+
 impl Pomodoro {
-    pub fn new(work_time: Duration, break_time: Duration) -> Self {
+    pub fn new(settings: PomodoroSettings) -> Self {
+        Self::new_with(settings, &SystemClock)
+    }
+
+    pub fn new_with(settings: PomodoroSettings, clock: &dyn Clock) -> Self {
+        let now = clock.now();
         Self {
-            start: Utc::now(),
-            work_time,
-            break_time,
+            id: Uuid::new_v4(),
+            start: now,
+            settings,
+            task: None,
+            phase: 0,
+            phase_start: Some(now),
+            paused: Duration::zero(),
+            paused_at: None,
+            events: vec![PomodoroEvent::Started(Phase::Work, now)],
+            history: Vec::new(),
+        }
+    }
+
+    fn phase_count(&self) -> usize {
+        2 * self.settings.cycles.max(1) as usize
+    }
+
+    /// The current phase, `None` once the session is done
+    pub fn phase(&self) -> Option<Phase> {
+        match self.phase {
+            i if i >= self.phase_count() => None,
+            i if i % 2 == 0 => Some(Phase::Work),
+            i if i + 1 == self.phase_count() => Some(Phase::LongBreak),
+            _ => Some(Phase::ShortBreak),
+        }
+    }
+
+    /// The current work cycle, counting from 1
+    pub fn cycle(&self) -> u32 {
+        (self.phase.min(self.phase_count() - 1) / 2) as u32 + 1
+    }
+
+    pub fn status_with(&self, clock: &dyn Clock) -> PomodoroStatus {
+        let now = clock.now();
+        match (self.phase(), self.phase_start) {
+            (None, _) => PomodoroStatus::Done,
+            (Some(phase), None) => PomodoroStatus::Waiting(phase),
+            (Some(phase), Some(start)) => {
+                PomodoroStatus::Running(phase, self.paused_at.unwrap_or(now) - start - self.paused)
+            }
         }
     }
 
     pub fn status(&self) -> PomodoroStatus {
-        let elapsed = Utc::now() - self.start;
-        if elapsed < self.work_time {
-            PomodoroStatus::Work(elapsed)
-        } else if elapsed < self.work_time + self.break_time {
-            PomodoroStatus::Break(elapsed - self.work_time)
-        } else {
-            PomodoroStatus::Done
+        self.status_with(&SystemClock)
+    }
+
+    /// The current phase as run until `end`, `None` while it waits to be started
+    fn record(&self, end: DateTime<Utc>, completed: bool) -> Option<PhaseRecord> {
+        let (Some(phase), Some(start)) = (self.phase(), self.phase_start) else {
+            return None;
+        };
+        let paused = self.paused + self.paused_at.map_or(Duration::zero(), |at| end - at);
+        Some(PhaseRecord {
+            phase,
+            start,
+            end,
+            paused: paused.num_seconds(),
+            completed,
+        })
+    }
+
+    /// Ends the current phase at `end` and moves on to the next one, started at `start` or
+    /// waiting to be started
+    fn advance(&mut self, end: DateTime<Utc>, completed: bool, start: Option<DateTime<Utc>>) {
+        self.history.extend(self.record(end, completed));
+        self.phase += 1;
+        self.paused = Duration::zero();
+        self.paused_at = None;
+        self.phase_start = start;
+        self.events.push(match (self.phase(), start) {
+            (None, _) => PomodoroEvent::Done,
+            (Some(phase), Some(start)) => PomodoroEvent::Started(phase, start),
+            (Some(phase), None) => PomodoroEvent::Waiting(phase),
+        });
+    }
+
+    pub fn poll_with(&mut self, clock: &dyn Clock) -> Vec<PomodoroEvent> {
+        let now = clock.now();
+        while let (Some(phase), Some(start), None) =
+            (self.phase(), self.phase_start, self.paused_at)
+        {
+            let end = start + self.paused + self.settings.duration(phase);
+            if now < end {
+                break;
+            }
+            self.events.push(PomodoroEvent::Completed(phase, end));
+            // Phases missed while the app was not running follow each other without gaps
+            self.advance(end, true, self.settings.auto_start.then_some(end));
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    /// Moves past phases that are over and returns the phase changes since the last poll
+    pub fn poll(&mut self) -> Vec<PomodoroEvent> {
+        self.poll_with(&SystemClock)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn pause_with(&mut self, clock: &dyn Clock) {
+        let now = clock.now();
+        if self.phase_start.is_some() && self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn resume_with(&mut self, clock: &dyn Clock) {
+        let now = clock.now();
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused = self.paused + (now - paused_at);
         }
     }
+
+    pub fn pause(&mut self) {
+        self.pause_with(&SystemClock);
+    }
+
+    pub fn resume(&mut self) {
+        self.resume_with(&SystemClock);
+    }
+
+    /// Ends the current phase early and starts the next one
+    pub fn skip_with(&mut self, clock: &dyn Clock) {
+        let now = clock.now();
+        if self.phase().is_some() {
+            self.advance(now, false, Some(now));
+        }
+    }
+
+    pub fn skip(&mut self) {
+        self.skip_with(&SystemClock);
+    }
+
+    /// Starts the phase waiting to be started
+    pub fn start_next_with(&mut self, clock: &dyn Clock) {
+        let now = clock.now();
+        if let (Some(phase), None) = (self.phase(), self.phase_start) {
+            self.phase_start = Some(now);
+            self.events.push(PomodoroEvent::Started(phase, now));
+        }
+    }
+
+    pub fn start_next(&mut self) {
+        self.start_next_with(&SystemClock);
+    }
+
+    /// The session as run until now, a running phase counts as not completed
+    pub fn session_with(&self, clock: &dyn Clock) -> Session {
+        let now = clock.now();
+        let phases: Vec<PhaseRecord> = self
+            .history
+            .iter()
+            .cloned()
+            .chain(self.record(now, false))
+            .collect();
+        Session {
+            id: self.id,
+            start: self.start,
+            end: phases.last().map_or(now, |p| p.end),
+            task: self.task,
+            phases,
+            completed: self.phase().is_none(),
+        }
+    }
+
+    pub fn session(&self) -> Session {
+        self.session_with(&SystemClock)
+    }
 }
 
 impl Default for Pomodoro {
     fn default() -> Self {
-        Self::new(Duration::minutes(25), Duration::minutes(5))
+        Self::new(PomodoroSettings::default())
+    }
+}
+
+/// Formats a duration as `MM:SS`
+pub fn format_minutes(d: Duration) -> String {
+    format!("{}:{:02}", d.num_minutes(), d.num_seconds() % 60)
+}
+
+/// Durations are stored as whole seconds
+mod seconds {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_seconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        i64::deserialize(deserializer).map(Duration::seconds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use crate::clock::ManualClock;
+
+    fn clock() -> ManualClock {
+        ManualClock::new(Utc.with_ymd_and_hms(2023, 5, 1, 9, 0, 0).unwrap())
+    }
+
+    /// The time `minutes` after the start of `pomo`
+    fn after(pomo: &Pomodoro, minutes: i64) -> DateTime<Utc> {
+        pomo.start + Duration::minutes(minutes)
+    }
+
+    /// Sets the clock to `minutes` after the start of `pomo`
+    fn at<'a>(clock: &'a ManualClock, pomo: &Pomodoro, minutes: i64) -> &'a ManualClock {
+        clock.set(pomo.start + Duration::minutes(minutes));
+        clock
+    }
+
+    #[test]
+    fn pause_and_skip() {
+        let clock = clock();
+        let mut pomo = Pomodoro::new_with(PomodoroSettings::default(), &clock);
+
+        pomo.pause_with(at(&clock, &pomo, 10));
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 40)),
+            PomodoroStatus::Running(Phase::Work, Duration::minutes(10))
+        );
+        pomo.resume_with(at(&clock, &pomo, 40));
+        assert!(!pomo.is_paused());
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 50)),
+            vec![PomodoroEvent::Started(Phase::Work, pomo.start)]
+        );
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 50)),
+            PomodoroStatus::Running(Phase::Work, Duration::minutes(20))
+        );
+
+        pomo.skip_with(at(&clock, &pomo, 50));
+        let session = pomo.session_with(at(&clock, &pomo, 51));
+        assert!(!session.completed);
+        assert_eq!(session.pomodoros(), 0);
+        assert_eq!(session.focus_time(), Duration::minutes(20));
+        assert_eq!(session.phases[1].duration(), Duration::minutes(1));
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 50)),
+            vec![PomodoroEvent::Started(Phase::ShortBreak, after(&pomo, 50))]
+        );
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 51)),
+            PomodoroStatus::Running(Phase::ShortBreak, Duration::minutes(1))
+        );
+    }
+
+    #[test]
+    fn session_cycles() {
+        let settings = PomodoroSettings {
+            cycles: 2,
+            ..Default::default()
+        };
+        let clock = clock();
+        let mut pomo = Pomodoro::new_with(settings, &clock);
+        pomo.poll_with(&clock);
+
+        // 25 work, 5 break, 25 work, 15 long break
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 54)),
+            vec![
+                PomodoroEvent::Completed(Phase::Work, after(&pomo, 25)),
+                PomodoroEvent::Started(Phase::ShortBreak, after(&pomo, 25)),
+                PomodoroEvent::Completed(Phase::ShortBreak, after(&pomo, 30)),
+                PomodoroEvent::Started(Phase::Work, after(&pomo, 30)),
+            ]
+        );
+        assert_eq!(pomo.cycle(), 2);
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 56)),
+            vec![
+                PomodoroEvent::Completed(Phase::Work, after(&pomo, 55)),
+                PomodoroEvent::Started(Phase::LongBreak, after(&pomo, 55)),
+            ]
+        );
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 70)),
+            vec![
+                PomodoroEvent::Completed(Phase::LongBreak, after(&pomo, 70)),
+                PomodoroEvent::Done
+            ]
+        );
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 70)),
+            PomodoroStatus::Done
+        );
+        assert_eq!(pomo.cycle(), 2);
+
+        let session = pomo.session_with(at(&clock, &pomo, 80));
+        assert!(session.completed);
+        assert_eq!(session.phases.len(), 4);
+        assert_eq!(session.end, pomo.start + Duration::minutes(70));
+        assert_eq!(session.pomodoros(), 2);
+        assert_eq!(session.focus_time(), Duration::minutes(50));
+    }
+
+    #[test]
+    fn find_presets() {
+        let mine = Preset {
+            name: "Classic".to_string(),
+            settings: PomodoroSettings {
+                work_time: Duration::seconds(90),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            Preset::find(&[], "Classic"),
+            Some(PomodoroSettings::default())
+        );
+        assert_eq!(
+            Preset::find(std::slice::from_ref(&mine), "Classic"),
+            Some(mine.settings)
+        );
+        assert_eq!(
+            Preset::find(&[], "90/20").map(|settings| settings.work_time),
+            Some(Duration::minutes(90))
+        );
+        assert_eq!(Preset::find(&[], "Unknown"), None);
+    }
+
+    #[test]
+    fn restore_running() {
+        let clock = clock();
+        let mut pomo = Pomodoro::new_with(PomodoroSettings::default(), &clock);
+        pomo.pause_with(at(&clock, &pomo, 10));
+        pomo.resume_with(at(&clock, &pomo, 15));
+        pomo.poll_with(at(&clock, &pomo, 15));
+
+        let saved = serde_json::to_string(&pomo).unwrap();
+        let mut restored: Pomodoro = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored, pomo);
+        // Phases that ended while the app was closed are caught up with
+        assert_eq!(
+            restored.poll_with(at(&clock, &pomo, 31)),
+            vec![
+                PomodoroEvent::Completed(Phase::Work, after(&pomo, 30)),
+                PomodoroEvent::Started(Phase::ShortBreak, after(&pomo, 30)),
+            ]
+        );
+        assert_eq!(
+            restored.status_with(at(&clock, &pomo, 31)),
+            PomodoroStatus::Running(Phase::ShortBreak, Duration::minutes(1))
+        );
+    }
+
+    #[test]
+    fn wait_without_auto_start() {
+        let settings = PomodoroSettings {
+            auto_start: false,
+            ..Default::default()
+        };
+        let clock = clock();
+        let mut pomo = Pomodoro::new_with(settings, &clock);
+        pomo.poll_with(&clock);
+
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 40)),
+            vec![
+                PomodoroEvent::Completed(Phase::Work, after(&pomo, 25)),
+                PomodoroEvent::Waiting(Phase::ShortBreak),
+            ]
+        );
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 40)),
+            PomodoroStatus::Waiting(Phase::ShortBreak)
+        );
+        pomo.start_next_with(at(&clock, &pomo, 40));
+        assert_eq!(
+            pomo.poll_with(at(&clock, &pomo, 44)),
+            vec![PomodoroEvent::Started(Phase::ShortBreak, after(&pomo, 40))]
+        );
+        assert_eq!(
+            pomo.status_with(at(&clock, &pomo, 44)),
+            PomodoroStatus::Running(Phase::ShortBreak, Duration::minutes(4))
+        );
+    }
+
+    #[test]
+    fn phase_duration_bounds() {
+        assert_eq!(
+            PomodoroSettings::phase_duration(1, 30, 5),
+            Duration::seconds(5405)
+        );
+        assert_eq!(
+            PomodoroSettings::phase_duration(0, 0, 0),
+            Duration::seconds(1)
+        );
+        let max = Duration::hours(PomodoroSettings::MAX_HOURS);
+        assert_eq!(PomodoroSettings::phase_duration(1000, 0, 0), max);
+        assert_eq!(PomodoroSettings::phase_duration(i64::MAX, 59, 59), max);
     }
 }
@@ -0,0 +1,157 @@
+//! Versioned on-disk representation of the task list
+//!
+//! Every stored task list is a [`Document`]. Documents written by older versions are brought up
+//! to [`VERSION`] by the migrations in [`MIGRATIONS`], fields written by newer versions are
+//! ignored.
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::History;
+use crate::notify::NotifySettings;
+use crate::pomodoro::{Pomodoro, PomodoroSettings, Preset, Session};
+use crate::task::Task;
+
+/// Version of the documents written by this build
+pub const VERSION: u32 = 3;
+
+/// Upgrades a document from the version at its index plus one to the next version
+type Migration = fn(&mut Document);
+
+/// `MIGRATIONS[0]` migrates version 1 to version 2 and so on
+const MIGRATIONS: [Migration; (VERSION - 1) as usize] = [v1_to_v2, v2_to_v3];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub tasks: Vec<Task>,
+    /// Undo history, only kept across restarts if enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<History>,
+    /// Pomodoro sessions run so far
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<Session>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pomodoro_settings: Option<PomodoroSettings>,
+    /// The pomodoro running when the document was saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pomodoro: Option<Pomodoro>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<NotifySettings>,
+    /// Presets saved by the user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pomodoro_presets: Vec<Preset>,
+}
+
+impl Document {
+    pub fn new(tasks: Vec<Task>) -> Self {
+        Self {
+            version: VERSION,
+            tasks,
+            history: None,
+            sessions: Vec::new(),
+            pomodoro_settings: None,
+            pomodoro: None,
+            notifications: None,
+            pomodoro_presets: Vec::new(),
+        }
+    }
+
+    /// Reads a document of any known version and migrates it to the current one
+    ///
+    /// Version 1 is the bare task array written before documents were versioned.
+    pub fn load(input: &str) -> Result<Self, serde_json::Error> {
+        let mut doc = if input.trim_start().starts_with('[') {
+            Document {
+                version: 1,
+                tasks: serde_json::from_str(input)?,
+                history: None,
+                sessions: Vec::new(),
+                pomodoro_settings: None,
+                pomodoro: None,
+                notifications: None,
+                pomodoro_presets: Vec::new(),
+            }
+        } else {
+            serde_json::from_str::<Document>(input)?
+        };
+        // Versions start at 1, there is no migration for anything below
+        if doc.version == 0 {
+            return Err(serde::de::Error::custom("invalid document version 0"));
+        }
+        while doc.version < VERSION {
+            MIGRATIONS[doc.version as usize - 1](&mut doc);
+            doc.version += 1;
+        }
+        Ok(doc)
+    }
+
+    /// Whether the document was written by a newer version, saving it drops what this version
+    /// does not understand
+    pub fn is_newer(&self) -> bool {
+        self.version > VERSION
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("documents always serialize")
+    }
+}
+
+/// Version 1 task lists only know start and finish time, not the tracked intervals
+fn v1_to_v2(doc: &mut Document) {
+    for task in doc.tasks.iter_mut() {
+        if task.get_intervals().is_empty() {
+            task.set_times(task.get_started(), task.get_finished());
+        }
+    }
+}
+
+/// Version 3 added pomodoro sessions, settings, presets and notifications, which version 2
+/// documents simply do not have
+fn v2_to_v3(_doc: &mut Document) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrate_v1() {
+        let mut task = Task::default();
+        task.start();
+        task.finish();
+        let legacy = serde_json::to_string(&vec![&task])
+            .unwrap()
+            .replace(",\"intervals\":[{", ",\"dropped\":[{");
+        assert!(!legacy.contains("intervals"));
+
+        let doc = Document::load(&legacy).unwrap();
+        assert_eq!(doc.version, VERSION);
+        assert_eq!(doc.tasks[0].get_intervals().len(), 1);
+        assert_eq!(doc.tasks[0].get_duration(), task.get_duration());
+    }
+
+    #[test]
+    fn migrate_v2() {
+        let v2 = r#"{"version":2,"tasks":[]}"#;
+        let doc = Document::load(v2).unwrap();
+        assert_eq!(doc, Document::new(Vec::new()));
+    }
+
+    #[test]
+    fn version_zero() {
+        let v0 = r#"{"version":0,"tasks":[]}"#;
+        assert!(Document::load(v0).is_err());
+    }
+
+    #[test]
+    fn newer_version() {
+        let doc = Document::new(vec![Task::default()]);
+        let newer = doc.to_json().replacen(
+            &format!("{{\"version\":{}", VERSION),
+            &format!("{{\"version\":{},\"labels\":[\"x\"]", VERSION + 1),
+            1,
+        );
+        let loaded = Document::load(&newer).unwrap();
+        assert!(loaded.is_newer());
+        assert_eq!(loaded.tasks, doc.tasks);
+    }
+}
@@ -0,0 +1,116 @@
+//! Pomodoro counts and focus time per day and week, from the session history
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate, TimeZone};
+
+use crate::pomodoro::{Phase, PhaseRecord, Session};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    /// Work phases run for their full duration
+    pub pomodoros: u32,
+    /// Time spent in work phases
+    pub focus: Duration,
+}
+
+impl Default for Totals {
+    fn default() -> Self {
+        Self {
+            pomodoros: 0,
+            focus: Duration::zero(),
+        }
+    }
+}
+
+impl Totals {
+    fn add(&mut self, record: &PhaseRecord) {
+        if record.phase != Phase::Work {
+            return;
+        }
+        if record.completed {
+            self.pomodoros += 1;
+        }
+        self.focus = self.focus + record.duration();
+    }
+}
+
+/// Totals per day in `tz`, phases count towards the day they started on
+pub fn daily<Tz: TimeZone>(sessions: &[Session], tz: &Tz) -> BTreeMap<NaiveDate, Totals> {
+    let mut days: BTreeMap<NaiveDate, Totals> = BTreeMap::new();
+    for record in sessions.iter().flat_map(|session| &session.phases) {
+        let day = record.start.with_timezone(tz).date_naive();
+        days.entry(day).or_default().add(record);
+    }
+    days
+}
+
+/// Totals per week in `tz`, keyed by the Monday starting the week
+pub fn weekly<Tz: TimeZone>(sessions: &[Session], tz: &Tz) -> BTreeMap<NaiveDate, Totals> {
+    let mut weeks: BTreeMap<NaiveDate, Totals> = BTreeMap::new();
+    for (day, totals) in daily(sessions, tz) {
+        let monday = day - Duration::days(day.weekday().num_days_from_monday().into());
+        let week = weeks.entry(monday).or_default();
+        week.pomodoros += totals.pomodoros;
+        week.focus = week.focus + totals.focus;
+    }
+    weeks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn work(day: u32, minutes: i64, completed: bool) -> PhaseRecord {
+        let start = Utc.with_ymd_and_hms(2023, 5, day, 9, 0, 0).unwrap();
+        PhaseRecord {
+            phase: Phase::Work,
+            start,
+            end: start + Duration::minutes(minutes),
+            paused: 0,
+            completed,
+        }
+    }
+
+    #[test]
+    fn days_and_weeks() {
+        let breather = PhaseRecord {
+            phase: Phase::ShortBreak,
+            ..work(1, 5, true)
+        };
+        // 2023-05-01 is a Monday
+        let phases = vec![
+            work(1, 25, true),
+            breather,
+            work(1, 10, false),
+            work(7, 25, true),
+            work(8, 25, true),
+        ];
+        let sessions = vec![Session {
+            id: Uuid::new_v4(),
+            start: phases[0].start,
+            end: phases[4].end,
+            task: None,
+            phases,
+            completed: false,
+        }];
+
+        let days = daily(&sessions, &Utc);
+        let monday = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[&monday].pomodoros, 1);
+        assert_eq!(days[&monday].focus, Duration::minutes(35));
+
+        let weeks = weekly(&sessions, &Utc);
+        let next = NaiveDate::from_ymd_opt(2023, 5, 8).unwrap();
+        assert_eq!(
+            weeks.keys().copied().collect::<Vec<_>>(),
+            vec![monday, next]
+        );
+        assert_eq!(weeks[&monday].pomodoros, 2);
+        assert_eq!(weeks[&next].focus, Duration::minutes(25));
+    }
+}
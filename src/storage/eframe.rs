@@ -0,0 +1,78 @@
+//! Store keeping the document in eframe's own key-value storage, as taskman did before it had a
+//! file of its own
+//!
+//! eframe only hands out its storage mutably while saving the app, so the store borrows it for
+//! the duration of a save.
+
+use super::TaskStore;
+use crate::error::Result;
+use crate::schema::Document;
+
+pub struct EframeStore<'a> {
+    storage: &'a mut dyn ::eframe::Storage,
+    key: &'static str,
+}
+
+impl<'a> EframeStore<'a> {
+    pub fn new(storage: &'a mut dyn ::eframe::Storage, key: &'static str) -> Self {
+        Self { storage, key }
+    }
+
+    /// Reads the document without borrowing the storage mutably, as needed while the app starts
+    pub fn read(storage: &dyn ::eframe::Storage, key: &str) -> Result<Option<Document>> {
+        storage
+            .get_string(key)
+            .map(|stored| Ok(Document::load(&stored)?))
+            .transpose()
+    }
+}
+
+impl TaskStore for EframeStore<'_> {
+    fn location(&self) -> String {
+        format!("eframe storage '{}'", self.key)
+    }
+
+    fn load(&mut self) -> Result<Option<Document>> {
+        EframeStore::read(self.storage, self.key)
+    }
+
+    fn save(&mut self, doc: &Document) -> Result<()> {
+        self.storage.set_string(self.key, doc.to_json());
+        self.storage.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::task::Task;
+
+    #[derive(Default)]
+    struct MapStorage(HashMap<String, String>);
+
+    impl ::eframe::Storage for MapStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut storage = MapStorage::default();
+        let doc = Document::new(vec![Task::default()]);
+        EframeStore::new(&mut storage, "task_list")
+            .save(&doc)
+            .unwrap();
+        assert_eq!(EframeStore::read(&storage, "task_list").unwrap(), Some(doc));
+        assert_eq!(EframeStore::read(&storage, "other").unwrap(), None);
+    }
+}
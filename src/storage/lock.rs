@@ -0,0 +1,92 @@
+//! Advisory lock keeping two windows from writing the same store
+//!
+//! The lock is held on a `.lock` file next to the store for as long as the [`StoreLock`] lives. A
+//! window that finds the store locked can ask the holder to come to the front through a `.focus`
+//! file, which the holder polls for.
+
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+fn sibling(store: &Path, suffix: &str) -> PathBuf {
+    let mut name = store.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    store.with_file_name(name)
+}
+
+pub struct StoreLock {
+    _file: File,
+    store: PathBuf,
+}
+
+impl StoreLock {
+    /// Locks the store at `path`, `None` if another process holds the lock
+    pub fn try_acquire(path: &Path) -> io::Result<Option<StoreLock>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(sibling(path, ".lock"))?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        // A request left over from before is not meant for this window
+        let _ = fs::remove_file(sibling(path, ".focus"));
+        Ok(Some(StoreLock {
+            _file: file,
+            store: path.to_path_buf(),
+        }))
+    }
+
+    /// Whether another window asked this one to come to the front, the request is consumed
+    pub fn focus_requested(&self) -> bool {
+        fs::remove_file(sibling(&self.store, ".focus")).is_ok()
+    }
+}
+
+/// The process id recorded by the holder of the lock on the store at `path`
+pub fn holder(path: &Path) -> Option<u32> {
+    fs::read_to_string(sibling(path, ".lock"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Asks the window holding the lock on the store at `path` to come to the front
+pub fn request_focus(path: &Path) -> io::Result<()> {
+    fs::write(sibling(path, ".focus"), std::process::id().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::storage::TempDir;
+
+    #[test]
+    fn exclusive_lock() {
+        let dir = TempDir::new();
+        let path = dir.join("tasks.db");
+
+        let lock = StoreLock::try_acquire(&path).unwrap().unwrap();
+        assert!(StoreLock::try_acquire(&path).unwrap().is_none());
+        assert_eq!(holder(&path), Some(std::process::id()));
+
+        assert!(!lock.focus_requested());
+        request_focus(&path).unwrap();
+        assert!(lock.focus_requested());
+        assert!(!lock.focus_requested());
+
+        drop(lock);
+        assert!(StoreLock::try_acquire(&path).unwrap().is_some());
+    }
+}
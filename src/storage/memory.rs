@@ -0,0 +1,82 @@
+//! Store keeping the document in memory only, for tests and throwaway sessions
+
+use uuid::Uuid;
+
+use super::TaskStore;
+use crate::error::Result;
+use crate::schema::Document;
+use crate::task::Task;
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    doc: Option<Document>,
+}
+
+impl MemoryStore {
+    pub fn new(doc: Option<Document>) -> Self {
+        Self { doc }
+    }
+
+    pub fn document(&self) -> Option<&Document> {
+        self.doc.as_ref()
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn load(&mut self) -> Result<Option<Document>> {
+        Ok(self.doc.clone())
+    }
+
+    fn save(&mut self, doc: &Document) -> Result<()> {
+        self.doc = Some(doc.clone());
+        Ok(())
+    }
+
+    fn incremental(&self) -> bool {
+        true
+    }
+
+    fn upsert(&mut self, task: &Task) -> Result<()> {
+        let doc = self.doc.get_or_insert_with(|| Document::new(Vec::new()));
+        match doc
+            .tasks
+            .iter_mut()
+            .find(|t| t.get_uuid() == task.get_uuid())
+        {
+            Some(stored) => *stored = task.clone(),
+            None => doc.tasks.push(task.clone()),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<()> {
+        if let Some(doc) = &mut self.doc {
+            doc.tasks.retain(|task| task.get_uuid() != id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upsert_and_delete() {
+        let mut store = MemoryStore::default();
+        assert_eq!(store.load().unwrap(), None);
+
+        let mut task = Task::default();
+        store.upsert(&task).unwrap();
+        task.name = "Changed".to_string();
+        store.upsert(&task).unwrap();
+        assert_eq!(store.load().unwrap().unwrap().tasks, vec![task.clone()]);
+
+        store.delete(task.get_uuid()).unwrap();
+        assert!(store.document().unwrap().tasks.is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+//! Where the task list is kept between runs, and its backups
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::schema::Document;
+use crate::task::Task;
+
+#[cfg(feature = "gui")]
+pub mod eframe;
+pub mod lock;
+pub mod memory;
+pub mod sqlite;
+pub mod watch;
+
+const BACKUP_PREFIX: &str = "tasks-";
+const BACKUP_SUFFIX: &str = ".json";
+const BACKUP_TIME_FMT: &str = "%Y%m%dT%H%M%S%.3f";
+
+/// A place the task list document is loaded from and saved to
+pub trait TaskStore {
+    /// Where the tasks are stored, for messages
+    fn location(&self) -> String;
+
+    /// The stored document, `None` if nothing has been stored yet
+    fn load(&mut self) -> Result<Option<Document>>;
+
+    /// Replaces the stored document, writing only what changed if the store supports it
+    fn save(&mut self, doc: &Document) -> Result<()>;
+
+    /// The file holding the tasks, watched for changes made by others
+    fn file(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Whether `upsert` and `delete` write right away
+    fn incremental(&self) -> bool {
+        false
+    }
+
+    /// Stores a single new or changed task
+    ///
+    /// Stores that can only write the whole document do nothing and rely on the next `save`.
+    fn upsert(&mut self, _task: &Task) -> Result<()> {
+        Ok(())
+    }
+
+    /// Removes a single task, see [`TaskStore::upsert`]
+    fn delete(&mut self, _id: Uuid) -> Result<()> {
+        Ok(())
+    }
+
+    /// Backs up the currently stored document, if the store keeps backups
+    fn backup(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn backups(&mut self) -> Option<&mut Backups> {
+        None
+    }
+}
+
+/// Opens the store for `path`, SQLite for `.db`, `.sqlite` and `.sqlite3` files and json
+/// otherwise
+pub fn open(path: &Path) -> Result<Box<dyn TaskStore>> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match ext.as_deref() {
+        Some("db" | "sqlite" | "sqlite3") => Ok(Box::new(sqlite::SqliteStore::open(path)?)),
+        _ => Ok(Box::new(DataFile::new(path))),
+    }
+}
+
+/// The platform's data directory, where eframe keeps the app's other persisted state as well
+pub fn data_dir(app_name: &str) -> Option<PathBuf> {
+    directories_next::ProjectDirs::from("", "", app_name).map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it over `path`, so `path`
+/// holds either the old or the new contents even if writing fails halfway
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            Err(err)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub time: DateTime<Utc>,
+}
+
+impl Backup {
+    pub fn read(&self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+/// Timestamped copies of a stored document, kept in a directory next to the store
+pub struct Backups {
+    dir: PathBuf,
+    /// Older backups are deleted
+    pub max: usize,
+    /// Saves within this time of the newest backup do not create another one
+    pub interval: Duration,
+}
+
+impl Backups {
+    /// The backups of the store at `path`, `tasks.json` keeps them in `tasks.backups`
+    pub fn new(path: &Path) -> Self {
+        Self {
+            dir: path.with_extension("backups"),
+            max: 10,
+            interval: Duration::hours(1),
+        }
+    }
+
+    /// Whether the newest backup is older than the backup interval
+    pub fn due(&self) -> io::Result<bool> {
+        Ok(self
+            .list()?
+            .first()
+            .is_none_or(|newest| Utc::now() - newest.time >= self.interval))
+    }
+
+    /// Writes a new backup and deletes the ones exceeding the maximum
+    pub fn create(&self, contents: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!(
+            "{}{}{}",
+            BACKUP_PREFIX,
+            Utc::now().format(BACKUP_TIME_FMT),
+            BACKUP_SUFFIX
+        );
+        write_atomic(&self.dir.join(name), contents)?;
+
+        for old in self.list()?.iter().skip(self.max) {
+            fs::remove_file(&old.path)?;
+        }
+        Ok(())
+    }
+
+    /// All backups, newest first
+    pub fn list(&self) -> io::Result<Vec<Backup>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let time = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(BACKUP_PREFIX))
+                .and_then(|name| name.strip_suffix(BACKUP_SUFFIX))
+                .and_then(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FMT).ok());
+            if let Some(time) = time {
+                backups.push(Backup {
+                    path,
+                    time: Utc.from_utc_datetime(&time),
+                });
+            }
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.time));
+        Ok(backups)
+    }
+}
+
+/// The task list as a single json file
+pub struct DataFile {
+    path: PathBuf,
+    backups: Backups,
+}
+
+impl DataFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            backups: Backups::new(path),
+        }
+    }
+
+    /// The saved contents, `None` if nothing has been saved yet
+    pub fn read(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Atomically replaces the saved contents, backing up the previous ones if a backup is due
+    pub fn write(&self, contents: &str) -> io::Result<()> {
+        match self.read()? {
+            Some(current) if current == contents => return Ok(()),
+            Some(current) => {
+                if self.backups.due()? {
+                    self.backups.create(&current)?;
+                }
+            }
+            None => fs::create_dir_all(self.path.parent().unwrap_or(Path::new(".")))?,
+        }
+        write_atomic(&self.path, contents)
+    }
+}
+
+impl TaskStore for DataFile {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn load(&mut self) -> Result<Option<Document>> {
+        let Some(contents) = self.read()? else {
+            return Ok(None);
+        };
+        Ok(Some(Document::load(&contents)?))
+    }
+
+    fn save(&mut self, doc: &Document) -> Result<()> {
+        Ok(self.write(&doc.to_json())?)
+    }
+
+    fn backup(&mut self) -> Result<()> {
+        if let Some(current) = self.read()? {
+            self.backups.create(&current)?;
+        }
+        Ok(())
+    }
+
+    fn backups(&mut self) -> Option<&mut Backups> {
+        Some(&mut self.backups)
+    }
+}
+
+/// A directory for tests, removed with everything in it when dropped, even if the test fails
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("taskman-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_and_backup() {
+        let dir = TempDir::new();
+        let file = DataFile::new(&dir.join("tasks.json"));
+        assert_eq!(file.read().unwrap(), None);
+
+        file.write("first").unwrap();
+        assert!(file.backups.list().unwrap().is_empty());
+        file.write("second").unwrap();
+        file.write("third").unwrap();
+        assert_eq!(file.read().unwrap().as_deref(), Some("third"));
+        // Only one backup per interval
+        let backups = file.backups.list().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].read().unwrap(), "first");
+        assert!(!dir.join("tasks.json.tmp").exists());
+    }
+
+    #[test]
+    fn prune_backups() {
+        let dir = TempDir::new();
+        let mut file = DataFile::new(&dir.join("tasks.json"));
+        file.backups.max = 2;
+        fs::create_dir_all(&file.backups.dir).unwrap();
+        for name in [
+            "tasks-20230101T100000.000.json",
+            "tasks-20230102T100000.000.json",
+            "notes.txt",
+        ] {
+            fs::write(file.backups.dir.join(name), "old").unwrap();
+        }
+        file.write("current").unwrap();
+        file.backup().unwrap();
+
+        let backups = file.backups.list().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].read().unwrap(), "current");
+        assert_eq!(
+            backups[1].time,
+            Utc.with_ymd_and_hms(2023, 1, 2, 10, 0, 0).unwrap()
+        );
+        assert!(file.backups.dir.join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,563 @@
+//! SQLite store keeping tasks, their intervals and subtask links in separate tables
+//!
+//! The store remembers the tasks it last loaded or saved, so saving only writes the rows of tasks
+//! that changed since.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use uuid::Uuid;
+
+use super::{Backups, TaskStore};
+use crate::error::Result;
+use crate::pomodoro::Session;
+use crate::schema::{Document, VERSION};
+use crate::task::{Interval, Task};
+
+/// Database schema changes, `SCHEMA[0]` creates version 1, tracked in `PRAGMA user_version`
+const SCHEMA: &[&str] = &[
+    "
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE tasks (
+        id TEXT PRIMARY KEY,
+        creation_time TEXT NOT NULL,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL,
+        started TEXT,
+        finished TEXT,
+        due TEXT,
+        priority TEXT,
+        projects TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        has_subtasks INTEGER NOT NULL
+    );
+    CREATE TABLE intervals (
+        task_id TEXT NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        start TEXT NOT NULL,
+        end TEXT,
+        PRIMARY KEY (task_id, position)
+    );
+    CREATE TABLE subtasks (
+        task_id TEXT NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        subtask_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (task_id, position)
+    );
+",
+    "
+    ALTER TABLE tasks ADD COLUMN pomodoros INTEGER NOT NULL DEFAULT 0;
+",
+    "
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        start TEXT NOT NULL,
+        end TEXT NOT NULL,
+        task_id TEXT,
+        completed INTEGER NOT NULL,
+        phases TEXT NOT NULL
+    );
+",
+    "
+    ALTER TABLE tasks ADD COLUMN pomodoro_preset TEXT;
+",
+];
+
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Connection,
+    backups: Backups,
+    /// Tasks as currently stored
+    saved: HashMap<Uuid, Task>,
+    /// Json of the document parts kept in the `meta` table, as currently stored
+    saved_meta: HashMap<&'static str, String>,
+    saved_sessions: HashSet<Uuid>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            conn,
+            backups: Backups::new(path),
+            saved: HashMap::new(),
+            saved_meta: HashMap::new(),
+            saved_sessions: HashSet::new(),
+        })
+    }
+
+    fn read(&self) -> rusqlite::Result<Option<Document>> {
+        let version: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let Some(version) = version else {
+            return Ok(None);
+        };
+        // Older documents are migrated by the schema, newer ones are kept newer for the warning
+        let version = version.parse().map_or(VERSION, |v: u32| v.max(VERSION));
+
+        let mut tasks: HashMap<Uuid, Task> = HashMap::new();
+        let mut order: Vec<Uuid> = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, creation_time, name, description, started, finished, due, priority,
+                projects, tags, has_subtasks, pomodoros, pomodoro_preset
+             FROM tasks ORDER BY creation_time, id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id = parse_uuid(row.get(0)?)?;
+            let priority: Option<String> = row.get(7)?;
+            let has_subtasks: bool = row.get(10)?;
+            let task = Task {
+                id,
+                creation_time: row.get(1)?,
+                name: row.get(2)?,
+                description: row.get(3)?,
+                started: row.get(4)?,
+                finished: row.get(5)?,
+                due: row.get(6)?,
+                priority: priority.and_then(|p| p.chars().next()),
+                projects: parse_json(row.get(8)?)?,
+                tags: parse_json(row.get(9)?)?,
+                intervals: Vec::new(),
+                subtasks: has_subtasks.then(Vec::new),
+                pomodoros: row.get(11)?,
+                pomodoro_preset: row.get(12)?,
+            };
+            order.push(id);
+            tasks.insert(id, task);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT task_id, start, end FROM intervals ORDER BY task_id, position")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(task) = tasks.get_mut(&parse_uuid(row.get(0)?)?) {
+                task.intervals.push(Interval {
+                    start: row.get(1)?,
+                    end: row.get(2)?,
+                });
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT task_id, subtask_id, name FROM subtasks ORDER BY task_id, position")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(task) = tasks.get_mut(&parse_uuid(row.get(0)?)?) {
+                task.subtasks
+                    .get_or_insert_with(Vec::new)
+                    .push((parse_uuid(row.get(1)?)?, row.get(2)?));
+            }
+        }
+
+        let meta = |key: &str| -> rusqlite::Result<Option<String>> {
+            self.conn
+                .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                    row.get(0)
+                })
+                .optional()
+        };
+        let history = meta("history")?.map(parse_json).transpose()?;
+        let pomodoro_settings = meta("pomodoro_settings")?.map(parse_json).transpose()?;
+        let pomodoro = meta("pomodoro")?.map(parse_json).transpose()?;
+        let notifications = meta("notifications")?.map(parse_json).transpose()?;
+        let pomodoro_presets = meta("pomodoro_presets")?
+            .map(parse_json)
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut sessions = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, start, end, task_id, completed, phases FROM sessions ORDER BY start, id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let task: Option<String> = row.get(3)?;
+            sessions.push(Session {
+                id: parse_uuid(row.get(0)?)?,
+                start: row.get(1)?,
+                end: row.get(2)?,
+                task: task.map(parse_uuid).transpose()?,
+                completed: row.get(4)?,
+                phases: parse_json(row.get(5)?)?,
+            });
+        }
+
+        Ok(Some(Document {
+            version,
+            tasks: order
+                .into_iter()
+                .filter_map(|id| tasks.remove(&id))
+                .collect(),
+            history,
+            sessions,
+            pomodoro_settings,
+            pomodoro,
+            notifications,
+            pomodoro_presets,
+        }))
+    }
+
+    /// Backs up the stored tasks before changing them, if the newest backup is old enough
+    fn backup_if_due(&mut self) -> Result<()> {
+        if !self.saved.is_empty() && self.backups.due()? {
+            self.backup()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, doc: &Document) -> rusqlite::Result<()> {
+        let meta = meta(doc);
+
+        let tx = self.conn.transaction()?;
+        write_version(&tx)?;
+        for (key, value) in &meta {
+            if self.saved_meta.get(key) == value.as_ref() {
+                continue;
+            }
+            match value {
+                Some(value) => tx.execute(
+                    "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                    params![key, value],
+                )?,
+                None => tx.execute("DELETE FROM meta WHERE key = ?1", [key])?,
+            };
+        }
+
+        let mut saved: HashMap<Uuid, Task> = HashMap::with_capacity(doc.tasks.len());
+        for task in &doc.tasks {
+            if self.saved.get(&task.id) != Some(task) {
+                write_task(&tx, task)?;
+            }
+            saved.insert(task.id, task.clone());
+        }
+        for id in self.saved.keys().filter(|id| !saved.contains_key(id)) {
+            tx.execute("DELETE FROM tasks WHERE id = ?1", [id.to_string()])?;
+        }
+        // Sessions are only ever added
+        for session in &doc.sessions {
+            if !self.saved_sessions.contains(&session.id) {
+                write_session(&tx, session)?;
+            }
+        }
+        tx.commit()?;
+
+        self.saved = saved;
+        self.saved_meta = saved_meta(meta);
+        self.saved_sessions = doc.sessions.iter().map(|session| session.id).collect();
+        Ok(())
+    }
+}
+
+/// Json of the document parts kept in the `meta` table by their keys, `None` for missing parts
+fn meta(doc: &Document) -> [(&'static str, Option<String>); 5] {
+    fn json<T: serde::Serialize>(value: &Option<T>) -> Option<String> {
+        value
+            .as_ref()
+            .map(|value| serde_json::to_string(value).expect("document parts always serialize"))
+    }
+    [
+        ("history", json(&doc.history)),
+        ("pomodoro_settings", json(&doc.pomodoro_settings)),
+        ("pomodoro", json(&doc.pomodoro)),
+        ("notifications", json(&doc.notifications)),
+        (
+            "pomodoro_presets",
+            json(&Some(&doc.pomodoro_presets).filter(|presets| !presets.is_empty())),
+        ),
+    ]
+}
+
+fn saved_meta(meta: [(&'static str, Option<String>); 5]) -> HashMap<&'static str, String> {
+    meta.into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+}
+
+fn write_version(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('version', ?1)",
+        [VERSION.to_string()],
+    )?;
+    Ok(())
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let tx = conn.transaction()?;
+    for (idx, sql) in SCHEMA.iter().enumerate().skip(version) {
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", idx + 1)?;
+    }
+    tx.commit()
+}
+
+fn write_task(tx: &Transaction, task: &Task) -> rusqlite::Result<()> {
+    let id = task.id.to_string();
+    // Deleting the row removes its intervals and subtask links as well
+    tx.execute("DELETE FROM tasks WHERE id = ?1", [&id])?;
+    tx.execute(
+        "INSERT INTO tasks (id, creation_time, name, description, started, finished, due,
+            priority, projects, tags, has_subtasks, pomodoros, pomodoro_preset)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            id,
+            task.creation_time,
+            task.name,
+            task.description,
+            task.started,
+            task.finished,
+            task.due,
+            task.priority.map(String::from),
+            serde_json::to_string(&task.projects).expect("strings always serialize"),
+            serde_json::to_string(&task.tags).expect("strings always serialize"),
+            task.subtasks.is_some(),
+            task.pomodoros,
+            task.pomodoro_preset,
+        ],
+    )?;
+    for (position, interval) in task.intervals.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO intervals (task_id, position, start, end) VALUES (?1, ?2, ?3, ?4)",
+            params![id, position, interval.start, interval.end],
+        )?;
+    }
+    for (position, (sub, name)) in task.subtasks.iter().flatten().enumerate() {
+        tx.execute(
+            "INSERT INTO subtasks (task_id, position, subtask_id, name) VALUES (?1, ?2, ?3, ?4)",
+            params![id, position, sub.to_string(), name],
+        )?;
+    }
+    Ok(())
+}
+
+fn write_session(tx: &Transaction, session: &Session) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO sessions (id, start, end, task_id, completed, phases)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            session.id.to_string(),
+            session.start,
+            session.end,
+            session.task.map(|id| id.to_string()),
+            session.completed,
+            serde_json::to_string(&session.phases).expect("phases always serialize"),
+        ],
+    )?;
+    Ok(())
+}
+
+fn parse_uuid(value: String) -> rusqlite::Result<Uuid> {
+    Uuid::parse_str(&value).map_err(conversion_error)
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(value: String) -> rusqlite::Result<T> {
+    serde_json::from_str(&value).map_err(conversion_error)
+}
+
+fn conversion_error(err: impl std::error::Error + Send + Sync + 'static) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(err))
+}
+
+impl TaskStore for SqliteStore {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn incremental(&self) -> bool {
+        true
+    }
+
+    fn load(&mut self) -> Result<Option<Document>> {
+        let doc = self.read()?;
+        if let Some(doc) = &doc {
+            self.saved = doc
+                .tasks
+                .iter()
+                .map(|task| (task.id, task.clone()))
+                .collect();
+            self.saved_meta = saved_meta(meta(doc));
+            self.saved_sessions = doc.sessions.iter().map(|session| session.id).collect();
+        }
+        Ok(doc)
+    }
+
+    fn save(&mut self, doc: &Document) -> Result<()> {
+        let changed = doc.tasks.len() != self.saved.len()
+            || doc
+                .tasks
+                .iter()
+                .any(|task| self.saved.get(&task.id) != Some(task));
+        if changed {
+            self.backup_if_due()?;
+        }
+        Ok(self.write(doc)?)
+    }
+
+    fn upsert(&mut self, task: &Task) -> Result<()> {
+        if self.saved.get(&task.id) == Some(task) {
+            return Ok(());
+        }
+        self.backup_if_due()?;
+        let tx = self.conn.transaction()?;
+        write_version(&tx)
+            .and_then(|_| write_task(&tx, task))
+            .and_then(|_| tx.commit())?;
+        self.saved.insert(task.id, task.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<()> {
+        if !self.saved.contains_key(&id) {
+            return Ok(());
+        }
+        self.backup_if_due()?;
+        self.conn
+            .execute("DELETE FROM tasks WHERE id = ?1", [id.to_string()])?;
+        self.saved.remove(&id);
+        Ok(())
+    }
+
+    fn backup(&mut self) -> Result<()> {
+        let Some(doc) = self.read()? else {
+            return Ok(());
+        };
+        Ok(self.backups.create(&doc.to_json())?)
+    }
+
+    fn backups(&mut self) -> Option<&mut Backups> {
+        Some(&mut self.backups)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::storage::TempDir;
+
+    use chrono::Utc;
+
+    use crate::notify::NotifySettings;
+    use crate::pomodoro::{Phase, PhaseRecord, Pomodoro, PomodoroSettings};
+
+    fn sample() -> Vec<Task> {
+        let sub = Task {
+            name: "Sub".to_string(),
+            tags: vec!["home".to_string()],
+            ..Default::default()
+        };
+        let mut parent = Task {
+            name: "Parent".to_string(),
+            description: "Two\nlines".to_string(),
+            priority: Some('B'),
+            projects: vec!["Garden".to_string()],
+            due: Some(Utc::now()),
+            ..Default::default()
+        };
+        parent
+            .add_subtask(sub.get_uuid(), sub.name.clone())
+            .unwrap();
+        parent.start();
+        parent.finish();
+        let mut tasks = vec![parent, sub];
+        tasks.sort_by_key(|task| (task.get_creation_time(), task.get_uuid()));
+        tasks
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new();
+        let path = dir.join("tasks.db");
+        let mut store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.load().unwrap(), None);
+
+        let mut doc = Document::new(sample());
+        doc.pomodoro_settings = Some(PomodoroSettings::default());
+        doc.notifications = Some(NotifySettings::default());
+        let mut pomodoro = Pomodoro::default();
+        // Unreported phase changes are not saved
+        pomodoro.poll();
+        doc.pomodoro = Some(pomodoro);
+        let now = Utc::now();
+        doc.sessions.push(Session {
+            id: Uuid::new_v4(),
+            start: now,
+            end: now,
+            task: Some(doc.tasks[0].get_uuid()),
+            phases: vec![PhaseRecord {
+                phase: Phase::Work,
+                start: now,
+                end: now,
+                paused: 0,
+                completed: false,
+            }],
+            completed: false,
+        });
+        store.save(&doc).unwrap();
+        let mut reopened = SqliteStore::open(&path).unwrap();
+        assert_eq!(reopened.load().unwrap(), Some(doc.clone()));
+
+        // Only the changed task is written, the removed one deleted with its rows
+        doc.pomodoro = None;
+        doc.tasks[0].name = "Renamed".to_string();
+        doc.tasks.remove(1);
+        reopened.save(&doc).unwrap();
+        assert_eq!(SqliteStore::open(&path).unwrap().load().unwrap(), Some(doc));
+        let intervals: usize = reopened
+            .conn
+            .query_row("SELECT COUNT(*) FROM intervals", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(intervals, 0);
+    }
+
+    #[test]
+    fn upsert_and_delete() {
+        let dir = TempDir::new();
+        let path = dir.join("tasks.db");
+        let mut store = SqliteStore::open(&path).unwrap();
+        let mut tasks = sample();
+
+        for task in &tasks {
+            store.upsert(task).unwrap();
+        }
+        tasks[1].name = "Changed".to_string();
+        store.upsert(&tasks[1]).unwrap();
+        store.delete(tasks[0].get_uuid()).unwrap();
+
+        let loaded = SqliteStore::open(&path).unwrap().load().unwrap().unwrap();
+        assert_eq!(loaded.tasks, vec![tasks[1].clone()]);
+
+        // A newer build wrote the database last
+        store
+            .conn
+            .execute(
+                "UPDATE meta SET value = ?1 WHERE key = 'version'",
+                [(VERSION + 1).to_string()],
+            )
+            .unwrap();
+        assert!(store.load().unwrap().unwrap().is_newer());
+    }
+}
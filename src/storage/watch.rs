@@ -0,0 +1,88 @@
+//! Noticing when the file of a store is changed by someone else
+//!
+//! Files are polled rather than watched through the platform, a changed modification time or size
+//! counts as a change.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    modified: SystemTime,
+    len: u64,
+}
+
+pub struct FileWatcher {
+    path: PathBuf,
+    seen: Option<Fingerprint>,
+}
+
+impl FileWatcher {
+    /// Starts watching `path` as it is now
+    pub fn new(path: &Path) -> Self {
+        let mut watcher = Self {
+            path: path.to_path_buf(),
+            seen: None,
+        };
+        watcher.reset();
+        watcher
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn fingerprint(&self) -> io::Result<Option<Fingerprint>> {
+        match fs::metadata(&self.path) {
+            Ok(meta) => Ok(Some(Fingerprint {
+                modified: meta.modified()?,
+                len: meta.len(),
+            })),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Takes the file as it is now as seen, to be called after writing it
+    pub fn reset(&mut self) {
+        self.seen = self.fingerprint().ok().flatten();
+    }
+
+    /// Whether the file changed since it was last seen
+    pub fn changed(&self) -> io::Result<bool> {
+        Ok(self.fingerprint()? != self.seen)
+    }
+
+    /// Whether the file changed since it was last seen, it is seen as changed only once
+    pub fn poll(&mut self) -> io::Result<bool> {
+        let current = self.fingerprint()?;
+        let changed = current != self.seen;
+        self.seen = current;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::storage::TempDir;
+
+    #[test]
+    fn poll_changes() {
+        let dir = TempDir::new();
+        let path = dir.join("tasks.json");
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!watcher.poll().unwrap());
+
+        fs::write(&path, "[]").unwrap();
+        assert!(watcher.poll().unwrap());
+        assert!(!watcher.poll().unwrap());
+
+        fs::write(&path, "[ ]").unwrap();
+        watcher.reset();
+        assert!(!watcher.poll().unwrap());
+    }
+}
@@ -1,23 +1,50 @@
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+use crate::clock::{Clock, SystemClock};
+use crate::error::TaskmanError;
+
 use serde::de;
 use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use serde::Deserialize;
 
-use eframe::egui;
-use egui::Color32;
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Task {
-    id: Uuid,
-    creation_time: DateTime<Utc>,
+    pub(crate) id: Uuid,
+    pub(crate) creation_time: DateTime<Utc>,
     pub name: String,
     pub description: String,
-    started: Option<DateTime<Utc>>,
-    finished: Option<DateTime<Utc>>,
+    pub(crate) started: Option<DateTime<Utc>>,
+    pub(crate) finished: Option<DateTime<Utc>>,
+    pub due: Option<DateTime<Utc>>,
+    /// Priority from `'A'` (highest) to `'Z'`
+    pub priority: Option<char>,
+    pub projects: Vec<String>,
+    pub tags: Vec<String>,
+    pub(crate) intervals: Vec<Interval>,
     pub subtasks: Option<Vec<(Uuid, String)>>,
+    /// Work phases of pomodoros completed on the task
+    pub(crate) pomodoros: u32,
+    /// Name of the pomodoro preset loaded when a pomodoro is linked to the task
+    pub pomodoro_preset: Option<String>,
+}
+
+/// A span of time spent working on a task, `end` is `None` while the clock is running
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, Deserialize)]
+pub struct Interval {
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Interval {
+    pub fn duration(&self) -> chrono::Duration {
+        self.duration_with(&SystemClock)
+    }
+
+    pub fn duration_with(&self, clock: &dyn Clock) -> chrono::Duration {
+        self.end.unwrap_or_else(|| clock.now()) - self.start
+    }
 }
 
 pub enum TaskStatus {
@@ -26,9 +53,17 @@ pub enum TaskStatus {
     Finished,
 }
 
-impl Task {
-    const DATEFMT: &str = "%d.%m.%Y %H:%M:%S";
+/// Formats a duration as `HH:MM:SS`
+pub fn format_duration(d: chrono::Duration) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        d.num_hours(),
+        d.num_minutes() % 60,
+        d.num_seconds() % 60
+    )
+}
 
+impl Task {
     pub fn get_uuid(&self) -> Uuid {
         self.id
     }
@@ -36,6 +71,14 @@ impl Task {
         self.creation_time
     }
 
+    pub fn get_started(&self) -> Option<DateTime<Utc>> {
+        self.started
+    }
+
+    pub fn get_finished(&self) -> Option<DateTime<Utc>> {
+        self.finished
+    }
+
     pub fn is_started(&self) -> bool {
         self.started.is_some()
     }
@@ -45,24 +88,116 @@ impl Task {
     }
 
     pub fn start(&mut self) {
+        self.start_with(&SystemClock);
+    }
+
+    pub fn start_with(&mut self, clock: &dyn Clock) {
         if self.is_started() || self.is_finished() {
             return;
         }
 
-        self.started = Some(Utc::now());
+        let now = clock.now();
+        self.started = Some(now);
+        self.intervals.push(Interval {
+            start: now,
+            end: None,
+        });
     }
 
     pub fn finish(&mut self) {
+        self.finish_with(&SystemClock);
+    }
+
+    pub fn finish_with(&mut self, clock: &dyn Clock) {
         if !self.is_started() || self.is_finished() {
             return;
         }
 
-        self.finished = Some(Utc::now());
+        let now = clock.now();
+        self.finished = Some(now);
+        for interval in self.intervals.iter_mut().filter(|i| i.end.is_none()) {
+            interval.end = Some(now);
+        }
+    }
+
+    /// Whether time is currently tracked on the task
+    pub fn is_running(&self) -> bool {
+        self.intervals.iter().any(|i| i.end.is_none())
+    }
+
+    /// Stops tracking time without finishing the task
+    pub fn pause(&mut self) {
+        self.pause_with(&SystemClock);
+    }
+
+    pub fn pause_with(&mut self, clock: &dyn Clock) {
+        let now = clock.now();
+        for interval in self.intervals.iter_mut().filter(|i| i.end.is_none()) {
+            interval.end = Some(now);
+        }
+    }
+
+    /// Tracks time again after a pause, starting the task if it was not started yet
+    pub fn resume(&mut self) {
+        self.resume_with(&SystemClock);
+    }
+
+    pub fn resume_with(&mut self, clock: &dyn Clock) {
+        if !self.is_started() {
+            self.start_with(clock);
+        } else if !self.is_finished() && !self.is_running() {
+            self.intervals.push(Interval {
+                start: clock.now(),
+                end: None,
+            });
+        }
+    }
+
+    pub fn get_pomodoros(&self) -> u32 {
+        self.pomodoros
+    }
+
+    pub fn add_pomodoro(&mut self) {
+        self.pomodoros += 1;
+    }
+
+    /// Sets start and finish time, tracking the time in between as a single interval
+    pub(crate) fn set_times(
+        &mut self,
+        started: Option<DateTime<Utc>>,
+        finished: Option<DateTime<Utc>>,
+    ) {
+        self.started = started;
+        self.finished = finished;
+        self.intervals = started
+            .map(|start| Interval {
+                start,
+                end: finished,
+            })
+            .into_iter()
+            .collect();
+    }
+
+    pub fn get_intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    /// Time spent on the task so far, including a currently running interval
+    pub fn tracked_time(&self) -> chrono::Duration {
+        self.tracked_time_with(&SystemClock)
+    }
+
+    pub fn tracked_time_with(&self, clock: &dyn Clock) -> chrono::Duration {
+        self.intervals
+            .iter()
+            .fold(chrono::Duration::zero(), |acc, i| {
+                acc + i.duration_with(clock)
+            })
     }
 
     pub fn get_duration(&self) -> Option<chrono::Duration> {
         if self.is_finished() {
-            Some(self.finished.unwrap() - self.started.unwrap())
+            Some(self.tracked_time())
         } else {
             None
         }
@@ -70,105 +205,37 @@ impl Task {
 
     pub fn status(&self) -> TaskStatus {
         match (self.is_started(), self.is_finished()) {
-            (true, true) => TaskStatus::Finished,
+            // Imported tasks may be finished without a start time
+            (_, true) => TaskStatus::Finished,
             (true, false) => TaskStatus::Started,
-            (false, true) => unreachable!(),
             (false, false) => TaskStatus::NotYet,
         }
     }
 
-    pub fn display(&self, ui: &mut egui::Ui) -> bool {
-        let mut clicked = false;
-        ui.vertical(|ui| {
-            clicked = clicked
-                | ui.add(
-                    egui::Label::new(
-                        if let Some(dur) = self.get_duration() {
-                            egui::RichText::new(format!(
-                                "{} | {} -> {} (Took {:02}:{:02}:{:02})",
-                                &DateTime::<Local>::from(self.creation_time).format(Task::DATEFMT),
-                                &DateTime::<Local>::from(self.started.unwrap())
-                                    .format(Task::DATEFMT),
-                                &DateTime::<Local>::from(self.finished.unwrap())
-                                    .format(Task::DATEFMT),
-                                dur.num_hours(),
-                                dur.num_minutes() - dur.num_hours() * 60,
-                                dur.num_seconds() - dur.num_minutes() * 60 - dur.num_hours() * 3600
-                            ))
-                            .color(Color32::DARK_GREEN)
-                        } else if let Some(begin) = self.started {
-                            egui::RichText::new(format!(
-                                "{} | {} -> ...",
-                                &DateTime::<Local>::from(self.creation_time).format(Task::DATEFMT),
-                                &DateTime::<Local>::from(begin).format(Task::DATEFMT),
-                            ))
-                            .color(Color32::from_rgb_additive(0x89, 0x38, 0x01))
-                        } else {
-                            egui::RichText::new(format!(
-                                "{}",
-                                &DateTime::<Local>::from(self.creation_time).format(Task::DATEFMT),
-                            ))
-                        }
-                        .text_style(egui::TextStyle::Name("Smaller".into())),
-                    )
-                    .sense(egui::Sense::click()),
-                )
-                .clicked();
-
-            clicked = clicked
-                | ui.add(
-                    egui::Label::new(
-                        egui::RichText::new(format!("{}", &self.name))
-                            .text_style(egui::TextStyle::Name("Heading2".into()))
-                            .strong(),
-                    )
-                    .sense(egui::Sense::click()),
-                )
-                .double_clicked();
-
-            clicked = clicked
-                | ui.add(
-                    egui::Label::new(egui::RichText::new(&self.description).strong())
-                        .sense(egui::Sense::click()),
-                )
-                .double_clicked();
-        });
-        clicked
-    }
-
     pub(crate) fn has_subtask(&self, id: Uuid) -> bool {
         match &self.subtasks {
-            Some(subtasks) => subtasks.iter().find(|(tid, _)| &id == tid).is_some(),
+            Some(subtasks) => subtasks.iter().any(|(tid, _)| &id == tid),
             None => false,
         }
     }
 
-    //TODO: proper error handling for subtask adding and removal
-    pub fn add_subtask(&mut self, id: Uuid, name: String) {
+    pub fn add_subtask(&mut self, id: Uuid, name: String) -> Result<(), TaskmanError> {
         if self.has_subtask(id) {
-            unreachable!("This function should not have been called like this");
+            return Err(TaskmanError::SubtaskExists(id));
         }
 
-        if let Some(subtasks) = &mut self.subtasks {
-            subtasks.push((id, name));
-        } else {
-            self.subtasks = Some(vec![(id, name)])
-        }
+        self.subtasks.get_or_insert_with(Vec::new).push((id, name));
+        Ok(())
     }
 
-    pub fn remove_subtask(&mut self, id: Uuid) {
-        if !self.has_subtask(id) {
-            unreachable!("Can only remove existing subtask");
-        }
-
-        if let Some(subtasks) = &mut self.subtasks {
-            let pos = subtasks
-                .iter()
-                .enumerate()
-                .find(|(_, (x, _))| x == &id)
-                .map(|(x, _)| x);
-            subtasks.remove(pos.unwrap());
-        }
+    pub fn remove_subtask(&mut self, id: Uuid) -> Result<(), TaskmanError> {
+        let subtasks = self
+            .subtasks
+            .as_mut()
+            .filter(|subtasks| subtasks.iter().any(|(sub, _)| *sub == id))
+            .ok_or(TaskmanError::SubtaskMissing(id))?;
+        subtasks.retain(|(sub, _)| *sub != id);
+        Ok(())
     }
 
     pub fn get_subtasks(&self) -> Option<&Vec<(Uuid, String)>> {
@@ -176,26 +243,40 @@ impl Task {
     }
 }
 
-impl Default for Task {
-    fn default() -> Self {
+impl Task {
+    /// A new task created at the time of `clock`
+    pub fn new_with(clock: &dyn Clock) -> Self {
         Self {
             id: Uuid::new_v4(),
-            creation_time: Utc::now(),
+            creation_time: clock.now(),
             name: "New Task".to_string(),
             description: "".to_string(),
             started: None,
             finished: None,
+            due: None,
+            priority: None,
+            projects: Vec::new(),
+            tags: Vec::new(),
+            intervals: Vec::new(),
             subtasks: None,
+            pomodoros: 0,
+            pomodoro_preset: None,
         }
     }
 }
 
+impl Default for Task {
+    fn default() -> Self {
+        Task::new_with(&SystemClock)
+    }
+}
+
 impl Serialize for Task {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("Task", 6)?;
+        let mut s = serializer.serialize_struct("Task", 14)?;
         s.serialize_field("id", &self.id.as_u128())?;
         s.serialize_field("creationtime", &self.creation_time)?;
         s.serialize_field("name", &self.name)?;
@@ -210,6 +291,13 @@ impl Serialize for Task {
                     .collect::<Vec<(u128, String)>>()
             }),
         )?;
+        s.serialize_field("due", &self.due)?;
+        s.serialize_field("priority", &self.priority)?;
+        s.serialize_field("projects", &self.projects)?;
+        s.serialize_field("tags", &self.tags)?;
+        s.serialize_field("intervals", &self.intervals)?;
+        s.serialize_field("pomodoros", &self.pomodoros)?;
+        s.serialize_field("pomodoro_preset", &self.pomodoro_preset)?;
         s.end()
     }
 }
@@ -229,6 +317,17 @@ impl<'de> Deserialize<'de> for Task {
             Started,
             Finished,
             Subtasks,
+            Due,
+            Priority,
+            Projects,
+            Tags,
+            Intervals,
+            Pomodoros,
+            #[serde(rename = "pomodoro_preset")]
+            PomodoroPreset,
+            /// Fields written by newer versions are skipped
+            #[serde(other)]
+            Unknown,
         }
 
         struct TaskVisitor;
@@ -264,6 +363,14 @@ impl<'de> Deserialize<'de> for Task {
                 let u_subtasks: Option<Vec<(u128, String)>> = seq
                     .next_element()?
                     .ok_or_else(|| de::Error::invalid_length(6, &self))?;
+                // Added after the initial format, missing in older task lists
+                let due: Option<DateTime<Utc>> = seq.next_element()?.flatten();
+                let priority: Option<char> = seq.next_element()?.flatten();
+                let projects: Vec<String> = seq.next_element()?.unwrap_or_default();
+                let tags: Vec<String> = seq.next_element()?.unwrap_or_default();
+                let intervals: Vec<Interval> = seq.next_element()?.unwrap_or_default();
+                let pomodoros: u32 = seq.next_element()?.unwrap_or_default();
+                let pomodoro_preset: Option<String> = seq.next_element()?.flatten();
 
                 let id = Uuid::from_u128(u_id);
                 let subtasks = u_subtasks.map(|mut u| {
@@ -279,7 +386,14 @@ impl<'de> Deserialize<'de> for Task {
                     description,
                     started,
                     finished,
+                    due,
+                    priority,
+                    projects,
+                    tags,
+                    intervals,
                     subtasks,
+                    pomodoros,
+                    pomodoro_preset,
                 })
             }
 
@@ -294,6 +408,13 @@ impl<'de> Deserialize<'de> for Task {
                 let mut started = None;
                 let mut finished = None;
                 let mut subtasks = None;
+                let mut due = None;
+                let mut priority = None;
+                let mut projects = None;
+                let mut tags = None;
+                let mut intervals = None;
+                let mut pomodoros = None;
+                let mut pomodoro_preset = None;
                 while let Some(key) = map.next_key::<Field>()? {
                     match key {
                         Field::Id => {
@@ -338,40 +459,96 @@ impl<'de> Deserialize<'de> for Task {
                             }
                             subtasks = Some(map.next_value::<Option<Vec<(u128, String)>>>()?);
                         }
+                        Field::Due => {
+                            if due.is_some() {
+                                return Err(de::Error::duplicate_field("due"));
+                            }
+                            due = Some(map.next_value::<Option<DateTime<Utc>>>()?);
+                        }
+                        Field::Priority => {
+                            if priority.is_some() {
+                                return Err(de::Error::duplicate_field("priority"));
+                            }
+                            priority = Some(map.next_value::<Option<char>>()?);
+                        }
+                        Field::Projects => {
+                            if projects.is_some() {
+                                return Err(de::Error::duplicate_field("projects"));
+                            }
+                            projects = Some(map.next_value()?);
+                        }
+                        Field::Tags => {
+                            if tags.is_some() {
+                                return Err(de::Error::duplicate_field("tags"));
+                            }
+                            tags = Some(map.next_value()?);
+                        }
+                        Field::Intervals => {
+                            if intervals.is_some() {
+                                return Err(de::Error::duplicate_field("intervals"));
+                            }
+                            intervals = Some(map.next_value::<Vec<Interval>>()?);
+                        }
+                        Field::Pomodoros => {
+                            if pomodoros.is_some() {
+                                return Err(de::Error::duplicate_field("pomodoros"));
+                            }
+                            pomodoros = Some(map.next_value::<u32>()?);
+                        }
+                        Field::PomodoroPreset => {
+                            if pomodoro_preset.is_some() {
+                                return Err(de::Error::duplicate_field("pomodoro_preset"));
+                            }
+                            pomodoro_preset = Some(map.next_value::<Option<String>>()?);
+                        }
+                        Field::Unknown => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
                     }
                 }
 
+                // Only the identity of a task is required, everything else may be missing in
+                // task lists written by older versions
                 Ok(Task {
                     id: id
-                        .map(|x| Uuid::from_u128(x))
+                        .map(Uuid::from_u128)
                         .ok_or_else(|| de::Error::missing_field("id"))?,
                     creation_time: creation_time
                         .ok_or_else(|| de::Error::missing_field("creationtime"))?,
-                    name: name.ok_or_else(|| de::Error::missing_field("name"))?,
-                    description: description
-                        .ok_or_else(|| de::Error::missing_field("description"))?,
-                    started: started.ok_or_else(|| de::Error::missing_field("started"))?,
-                    finished: finished.ok_or_else(|| de::Error::missing_field("finished"))?,
-                    subtasks: subtasks
-                        .map(|o| {
-                            o.map(|mut x| {
-                                x.drain(..)
-                                    .map(|(u, n)| (Uuid::from_u128(u), n.clone()))
-                                    .collect::<Vec<(Uuid, String)>>()
-                            })
-                        })
-                        .ok_or_else(|| de::Error::missing_field("subtasks"))?,
+                    name: name.unwrap_or_default(),
+                    description: description.unwrap_or_default(),
+                    started: started.flatten(),
+                    finished: finished.flatten(),
+                    due: due.flatten(),
+                    priority: priority.flatten(),
+                    projects: projects.unwrap_or_default(),
+                    tags: tags.unwrap_or_default(),
+                    intervals: intervals.unwrap_or_default(),
+                    subtasks: subtasks.flatten().map(|ids| {
+                        ids.into_iter()
+                            .map(|(u, n)| (Uuid::from_u128(u), n))
+                            .collect::<Vec<(Uuid, String)>>()
+                    }),
+                    pomodoros: pomodoros.unwrap_or_default(),
+                    pomodoro_preset: pomodoro_preset.flatten(),
                 })
             }
         }
 
-        const FIELDS: &'static [&'static str] = &[
+        const FIELDS: &[&str] = &[
             "id",
             "name",
             "description",
             "started",
             "finished",
             "subtasks",
+            "due",
+            "priority",
+            "projects",
+            "tags",
+            "intervals",
+            "pomodoros",
+            "pomodoro_preset",
         ];
 
         deserializer.deserialize_struct("task", FIELDS, TaskVisitor)
@@ -382,6 +559,10 @@ impl<'de> Deserialize<'de> for Task {
 mod test {
     use super::*;
 
+    use chrono::TimeZone;
+
+    use crate::clock::ManualClock;
+
     #[test]
     fn ser_de() {
         let task = Task::default();
@@ -389,4 +570,74 @@ mod test {
         let des_d = serde_json::from_str::<Task>(&ser_d).unwrap();
         assert_eq!(task, des_d)
     }
+
+    #[test]
+    fn de_legacy() {
+        let task = Task::default();
+        let ser_d = serde_json::to_string(&task).unwrap().replace(
+            ",\"due\":null,\"priority\":null,\"projects\":[],\"tags\":[],\"intervals\":[],\"pomodoros\":0,\"pomodoro_preset\":null",
+            "",
+        );
+        assert!(!ser_d.contains("due") && !ser_d.contains("tags"));
+        let des_d = serde_json::from_str::<Task>(&ser_d).unwrap();
+        assert_eq!(task, des_d)
+    }
+
+    #[test]
+    fn subtasks() {
+        let mut task = Task::default();
+        let sub = Uuid::new_v4();
+        task.add_subtask(sub, "Sub".to_string()).unwrap();
+        assert!(matches!(
+            task.add_subtask(sub, "Again".to_string()),
+            Err(TaskmanError::SubtaskExists(id)) if id == sub
+        ));
+        task.remove_subtask(sub).unwrap();
+        assert!(matches!(
+            task.remove_subtask(sub),
+            Err(TaskmanError::SubtaskMissing(_))
+        ));
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2023, 5, 1, 9, 0, 0).unwrap());
+        let minutes = |m| clock.advance(chrono::Duration::minutes(m));
+        let mut task = Task::new_with(&clock);
+        minutes(5);
+        task.resume_with(&clock);
+        assert!(task.is_started() && task.is_running());
+        minutes(20);
+        task.pause_with(&clock);
+        assert!(!task.is_running());
+        minutes(10);
+        task.resume_with(&clock);
+        assert_eq!(task.get_intervals().len(), 2);
+        minutes(5);
+        assert_eq!(
+            task.tracked_time_with(&clock),
+            chrono::Duration::minutes(25)
+        );
+        task.finish_with(&clock);
+        minutes(5);
+        task.resume_with(&clock);
+        assert!(!task.is_running());
+        assert_eq!(task.get_intervals().len(), 2);
+        assert_eq!(task.get_duration(), Some(chrono::Duration::minutes(25)));
+        assert_eq!(
+            task.get_finished().unwrap() - task.get_creation_time(),
+            chrono::Duration::minutes(40)
+        );
+    }
+
+    #[test]
+    fn de_unknown_fields() {
+        let task = Task::default();
+        let ser_d =
+            serde_json::to_string(&task)
+                .unwrap()
+                .replacen('{', "{\"color\":{\"r\":255},", 1);
+        let des_d = serde_json::from_str::<Task>(&ser_d).unwrap();
+        assert_eq!(task, des_d)
+    }
 }
@@ -0,0 +1,107 @@
+//! egui rendering of the core types
+
+use chrono::{DateTime, Local, Utc};
+use eframe::egui;
+use egui::Color32;
+
+use crate::task::{format_duration, Task, TaskStatus};
+
+const DATEFMT: &str = "%d.%m.%Y %H:%M:%S";
+
+pub const CLR_DONE: Color32 = Color32::DARK_GREEN;
+pub const CLR_INPROGRESS: Color32 = Color32::from_rgb_additive(0x89, 0x38, 0x01);
+pub const CLR_NOTSTARTED: Color32 = Color32::DARK_GRAY;
+
+pub fn status_color(status: &TaskStatus) -> Color32 {
+    match status {
+        TaskStatus::NotYet => CLR_NOTSTARTED,
+        TaskStatus::Started => CLR_INPROGRESS,
+        TaskStatus::Finished => CLR_DONE,
+    }
+}
+
+fn local(time: DateTime<Utc>) -> String {
+    DateTime::<Local>::from(time).format(DATEFMT).to_string()
+}
+
+/// Shows times, labels, name and description of a task, returns whether it was clicked to be
+/// edited
+pub fn task_summary(task: &Task, ui: &mut egui::Ui) -> bool {
+    let mut clicked = false;
+    ui.vertical(|ui| {
+        let created = local(task.get_creation_time());
+        let times = match (task.get_started(), task.get_finished()) {
+            (Some(started), Some(finished)) => egui::RichText::new(format!(
+                "{} | {} -> {} (Took {})",
+                created,
+                local(started),
+                local(finished),
+                format_duration(task.tracked_time())
+            ))
+            .color(CLR_DONE),
+            (Some(started), None) => {
+                egui::RichText::new(format!("{} | {} -> ...", created, local(started)))
+                    .color(CLR_INPROGRESS)
+            }
+            (None, Some(finished)) => {
+                egui::RichText::new(format!("{} | done {}", created, local(finished)))
+                    .color(CLR_DONE)
+            }
+            (None, None) => egui::RichText::new(created),
+        };
+        clicked |= ui
+            .add(
+                egui::Label::new(times.text_style(egui::TextStyle::Name("Smaller".into())))
+                    .sense(egui::Sense::click()),
+            )
+            .clicked();
+
+        let labels = task
+            .priority
+            .map(|p| format!("({})", p))
+            .into_iter()
+            .chain(task.projects.iter().map(|p| format!("+{}", p)))
+            .chain(task.tags.iter().map(|t| format!("@{}", t)))
+            .chain(match task.get_pomodoros() {
+                0 => None,
+                1 => Some("1 pomodoro".to_string()),
+                n => Some(format!("{} pomodoros", n)),
+            })
+            .collect::<Vec<String>>();
+        if !labels.is_empty() {
+            ui.label(
+                egui::RichText::new(labels.join(" "))
+                    .text_style(egui::TextStyle::Name("Smaller".into())),
+            );
+        }
+
+        if let Some(due) = task.due {
+            let due_text = egui::RichText::new(format!("Due {}", local(due)))
+                .text_style(egui::TextStyle::Name("Smaller".into()));
+            ui.label(if !task.is_finished() && due < Utc::now() {
+                due_text.color(Color32::DARK_RED)
+            } else {
+                due_text
+            });
+        }
+
+        clicked |= ui
+            .add(
+                egui::Label::new(
+                    egui::RichText::new(&task.name)
+                        .text_style(egui::TextStyle::Name("Heading2".into()))
+                        .strong(),
+                )
+                .sense(egui::Sense::click()),
+            )
+            .double_clicked();
+
+        clicked |= ui
+            .add(
+                egui::Label::new(egui::RichText::new(&task.description).strong())
+                    .sense(egui::Sense::click()),
+            )
+            .double_clicked();
+    });
+    clicked
+}
@@ -0,0 +1,1169 @@
+//! The open task list and everything that changes it, independent of the front-end showing it
+//!
+//! A [`Workspace`] applies changes as commands that can be undone, writes them to its
+//! [`TaskStore`] and event log, merges changes others made to the store and runs the pomodoro
+//! tracking the time of its task. Failures are kept for the front-end to show, see
+//! [`Workspace::take_error`].
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::clock::{Clock, FixedClock, SystemClock};
+use crate::collection::TaskCollection;
+use crate::command::{Change, Command, History};
+use crate::error;
+use crate::events::{self, Event, EventKind, EventLog};
+use crate::import::{ImportPreview, Resolution};
+use crate::merge;
+use crate::notify::{Notifier, NotifySettings};
+use crate::pomodoro::{
+    Phase, Pomodoro, PomodoroEvent, PomodoroSettings, PomodoroStatus, Preset, Session,
+};
+use crate::schema::Document;
+use crate::storage::lock::StoreLock;
+use crate::storage::watch::FileWatcher;
+use crate::storage::{self, Backups, TaskStore};
+use crate::task::Task;
+
+pub struct Workspace {
+    tasks: TaskCollection,
+    history: History,
+    /// The undo history is saved with the tasks
+    pub persist_history: bool,
+    store: Option<Box<dyn TaskStore>>,
+    pub max_backups: usize,
+    /// Set when the stored task list could not be read, it is never overwritten then
+    load_failed: bool,
+    event_log: Option<EventLog>,
+    author: String,
+    watcher: Option<FileWatcher>,
+    /// The stored tasks as last loaded or saved, changes made elsewhere are merged relative to it
+    synced: HashMap<Uuid, Task>,
+    lock: Option<StoreLock>,
+    /// Another window holds the lock on the store, nothing is saved or logged then
+    read_only: bool,
+    edit: Option<Uuid>,
+    /// The edited task as of the last recorded change, text edits are recorded once committed
+    edit_snapshot: Option<Task>,
+    /// Logged events of the edited task
+    edit_events: Vec<Event>,
+    pomodoro: Option<Pomodoro>,
+    pub pomodoro_settings: PomodoroSettings,
+    /// Presets saved by the user
+    pub pomodoro_presets: Vec<Preset>,
+    /// Finished and stopped pomodoro sessions
+    sessions: Vec<Session>,
+    pub notify_settings: NotifySettings,
+    /// Tasks changed both here and in the store, for the user to choose which versions to keep
+    conflicts: Option<ImportPreview>,
+    /// Tasks changed since they were last saved
+    unsaved: bool,
+    /// Everything but the tasks as last saved, a save without changes is skipped
+    saved: Option<Document>,
+    error: Option<String>,
+    /// Where the time of task and pomodoro changes comes from
+    clock: Box<dyn Clock>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self {
+            tasks: TaskCollection::new(),
+            history: History::default(),
+            persist_history: false,
+            store: None,
+            max_backups: 10,
+            load_failed: false,
+            event_log: None,
+            author: events::current_author(),
+            watcher: None,
+            synced: HashMap::new(),
+            lock: None,
+            read_only: false,
+            edit: None,
+            edit_snapshot: None,
+            edit_events: Vec::new(),
+            pomodoro: None,
+            pomodoro_settings: PomodoroSettings::default(),
+            pomodoro_presets: Vec::new(),
+            sessions: Vec::new(),
+            notify_settings: NotifySettings::default(),
+            conflicts: None,
+            unsaved: false,
+            saved: None,
+            error: None,
+            clock: Box::new(SystemClock),
+        }
+    }
+}
+
+impl Workspace {
+    /// Opens the store at `path` with its event log, read-only if another window has it locked
+    pub fn open(path: &Path) -> Self {
+        let mut ws = Self::default();
+        match ws.lock_store(path).and_then(|_| storage::open(path)) {
+            Ok(store) => {
+                ws.event_log = Some(EventLog::new(&path.with_extension("events.jsonl")));
+                ws.store = Some(store);
+            }
+            Err(err) => ws.fail_load(format!(
+                "Could not open '{}', changes will not be saved: {}",
+                path.display(),
+                err
+            )),
+        }
+        ws
+    }
+
+    /// A workspace on `store`, without a lock or an event log
+    pub fn with_store(store: Box<dyn TaskStore>) -> Self {
+        Self {
+            store: Some(store),
+            ..Default::default()
+        }
+    }
+
+    /// Takes the time from `clock` instead of the system
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Keeps the workspace from ever saving, because the stored task list is unknown
+    pub fn fail_load(&mut self, msg: String) {
+        self.load_failed = true;
+        self.error = Some(msg);
+    }
+
+    /// Loads the tasks and settings from the store, an empty store migrates the task list
+    /// returned by `legacy`
+    pub fn load(&mut self, legacy: impl FnOnce() -> Option<String>) {
+        let stored = self
+            .store
+            .as_mut()
+            .map(|store| (store.load(), store.location()));
+        self.load_stored(stored, legacy);
+    }
+
+    /// Like [`Workspace::load`], for a document read from a store the workspace does not own
+    pub fn load_stored(
+        &mut self,
+        stored: Option<(error::Result<Option<Document>>, String)>,
+        legacy: impl FnOnce() -> Option<String>,
+    ) {
+        let mut migrated = false;
+        let loaded = match stored {
+            Some((Ok(Some(doc)), _)) => {
+                self.check_version(&doc);
+                Some(doc)
+            }
+            Some((Ok(None), _)) => match legacy() {
+                Some(input) => {
+                    migrated = true;
+                    self.load_document(&input)
+                }
+                None => None,
+            },
+            None => None,
+            Some((Err(err), location)) => {
+                self.fail_load(format!(
+                    "Could not read '{}', changes will not be saved: {}",
+                    location, err
+                ));
+                None
+            }
+        };
+        match loaded {
+            Some(doc) => {
+                self.tasks = doc.tasks.into_iter().collect();
+                if !migrated {
+                    self.synced = (*self.tasks).clone();
+                }
+                // Links to missing tasks or closing a cycle are repaired on the next save
+                let ids: Vec<Uuid> = self.tasks.keys().copied().collect();
+                let dropped = self.tasks.drop_broken_links(ids);
+                if !dropped.is_empty() {
+                    self.unsaved = true;
+                    self.error.get_or_insert_with(|| {
+                        format!(
+                            "Removed {} subtask links to missing tasks or forming a cycle",
+                            dropped.len()
+                        )
+                    });
+                }
+                if self.persist_history {
+                    self.history = doc.history.unwrap_or_default();
+                }
+                self.sessions = doc.sessions;
+                if let Some(settings) = doc.pomodoro_settings {
+                    self.pomodoro_settings = settings;
+                }
+                // Phases that ended while the app was closed are caught up with on the next poll
+                self.pomodoro = doc.pomodoro;
+                if let Some(settings) = doc.notifications {
+                    self.notify_settings = settings;
+                }
+                self.pomodoro_presets = doc.pomodoro_presets;
+                if migrated {
+                    self.save();
+                }
+            }
+            None if migrated => self.load_failed = true,
+            None => (),
+        }
+        self.watcher = self
+            .store
+            .as_ref()
+            .and_then(|store| store.file())
+            .map(FileWatcher::new);
+        // Without a log yet, it starts with the tasks as they are
+        if !self.load_failed && self.event_log.as_ref().is_some_and(|log| !log.exists()) {
+            let now = self.clock.now();
+            let recorded: Vec<Event> = self
+                .tasks
+                .values()
+                .map(|task| Event {
+                    time: now,
+                    author: self.author.clone(),
+                    id: task.get_uuid(),
+                    kinds: vec![EventKind::Recorded],
+                    task: Some(task.clone()),
+                })
+                .collect();
+            self.append_events(recorded);
+        }
+    }
+
+    fn check_version(&mut self, doc: &Document) {
+        if doc.is_newer() {
+            self.error = Some(format!(
+                "The task list was written by a newer version of taskman (format version {}), data this version does not know is lost when saving",
+                doc.version
+            ));
+        }
+    }
+
+    fn load_document(&mut self, input: &str) -> Option<Document> {
+        match Document::load(input) {
+            Ok(doc) => {
+                self.check_version(&doc);
+                Some(doc)
+            }
+            Err(err) => {
+                self.error = Some(format!(
+                    "Could not read the stored task list, changes will not be saved: {}",
+                    err
+                ));
+                None
+            }
+        }
+    }
+
+    pub fn tasks(&self) -> &TaskCollection {
+        &self.tasks
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    pub fn pomodoro(&self) -> Option<&Pomodoro> {
+        self.pomodoro.as_ref()
+    }
+
+    pub fn store(&self) -> Option<&dyn TaskStore> {
+        self.store.as_deref()
+    }
+
+    /// Closes the workspace, handing back its store
+    pub fn into_store(self) -> Option<Box<dyn TaskStore>> {
+        self.store
+    }
+
+    /// The backups kept by the store, `None` if it keeps none
+    pub fn backups(&mut self) -> Option<&mut Backups> {
+        self.store.as_mut().and_then(|store| store.backups())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The last failure since it was taken
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Conflicting changes found while merging those made elsewhere, since they were taken
+    pub fn take_conflicts(&mut self) -> Option<ImportPreview> {
+        self.conflicts.take()
+    }
+
+    /// Locks the store at `path` or, if another window has it locked, opens it read-only
+    fn lock_store(&mut self, path: &Path) -> error::Result<()> {
+        match StoreLock::try_acquire(path)? {
+            Some(lock) => {
+                self.lock = Some(lock);
+                self.read_only = false;
+            }
+            None => self.read_only = true,
+        }
+        Ok(())
+    }
+
+    /// Leaves read-only mode once the window holding the lock is closed
+    fn retry_lock(&mut self) {
+        let Some(path) = self
+            .store
+            .as_ref()
+            .and_then(|store| store.file())
+            .map(|path| path.to_path_buf())
+        else {
+            return;
+        };
+        match StoreLock::try_acquire(&path) {
+            Ok(Some(lock)) => {
+                self.lock = Some(lock);
+                self.read_only = false;
+                // Whatever the other window saved last is merged in on the next check
+                if let Some(watcher) = &mut self.watcher {
+                    watcher.reset();
+                }
+                self.reload();
+            }
+            Ok(None) => (),
+            Err(err) => {
+                self.error = Some(format!("Could not lock '{}': {}", path.display(), err));
+            }
+        }
+    }
+
+    /// Whether another window asked this one to come to the front, the request is consumed
+    pub fn focus_requested(&self) -> bool {
+        self.lock
+            .as_ref()
+            .is_some_and(|lock| lock.focus_requested())
+    }
+
+    /// The document to save, `None` if the stored one must not be overwritten
+    pub fn document(&self) -> Option<Document> {
+        let mut doc = self.settings_document()?;
+        // Keep the file stable so unchanged task lists are not written again
+        doc.tasks = self.tasks.sorted().into_iter().cloned().collect();
+        Some(doc)
+    }
+
+    /// The document to save without its tasks
+    fn settings_document(&self) -> Option<Document> {
+        if self.load_failed || self.read_only {
+            return None;
+        }
+        let mut doc = Document::new(Vec::new());
+        if self.persist_history {
+            doc.history = Some(self.history.clone());
+        }
+        doc.sessions = self.sessions.clone();
+        doc.pomodoro_settings = Some(self.pomodoro_settings.clone());
+        doc.pomodoro = self.pomodoro.clone();
+        doc.notifications = Some(self.notify_settings.clone());
+        doc.pomodoro_presets = self.pomodoro_presets.clone();
+        Some(doc)
+    }
+
+    /// The document to save, `None` if nothing changed since the last save as well
+    fn unsaved_document(&self) -> Option<Document> {
+        let settings = self.settings_document()?;
+        // Text edits are saved before they are committed
+        let editing = self
+            .edit
+            .is_some_and(|id| self.tasks.get(&id) != self.edit_snapshot.as_ref());
+        if !self.unsaved && !editing && self.saved.as_ref() == Some(&settings) {
+            return None;
+        }
+        self.document()
+    }
+
+    /// Takes `doc` as the last saved document
+    fn saved(&mut self, mut doc: Document) {
+        doc.tasks.clear();
+        self.saved = Some(doc);
+        self.unsaved = false;
+    }
+
+    /// Saves everything to the store
+    pub fn save(&mut self) {
+        // Changes made elsewhere would be overwritten otherwise
+        self.check_external();
+        let Some(doc) = self.unsaved_document() else {
+            return;
+        };
+        if let Some(store) = &mut self.store {
+            match save_document(store.as_mut(), &doc, self.max_backups) {
+                Ok(()) => {
+                    self.synced = (*self.tasks).clone();
+                    if let Some(watcher) = &mut self.watcher {
+                        watcher.reset();
+                    }
+                    self.saved(doc);
+                }
+                Err(err) => self.error = Some(err),
+            }
+        }
+    }
+
+    /// Saves everything to a store the workspace does not own, such as eframe's storage
+    pub fn save_to(&mut self, store: &mut dyn TaskStore) {
+        let Some(doc) = self.unsaved_document() else {
+            return;
+        };
+        match save_document(store, &doc, self.max_backups) {
+            Ok(()) => self.saved(doc),
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    /// Merges changes another program or window made to the store file since it was last loaded
+    /// or saved
+    pub fn check_external(&mut self) {
+        if self.read_only {
+            self.retry_lock();
+        }
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        match watcher.poll() {
+            Ok(true) => self.reload(),
+            Ok(false) => (),
+            Err(err) => {
+                self.error = Some(format!(
+                    "Could not check '{}' for changes: {}",
+                    watcher.path().display(),
+                    err
+                ))
+            }
+        }
+    }
+
+    fn reload(&mut self) {
+        if self.load_failed {
+            return;
+        }
+        let Some(store) = &mut self.store else {
+            return;
+        };
+        let location = store.location();
+        let theirs: HashMap<Uuid, Task> = match store.load() {
+            Ok(doc) => {
+                let doc = doc.unwrap_or_else(|| Document::new(Vec::new()));
+                // Sessions are only ever added, so all of them are kept
+                for session in doc.sessions {
+                    if !self.sessions.iter().any(|s| s.id == session.id) {
+                        self.sessions.push(session);
+                    }
+                }
+                self.sessions.sort_by_key(|s| s.start);
+                doc.tasks
+                    .into_iter()
+                    .map(|task| (task.get_uuid(), task))
+                    .collect()
+            }
+            Err(err) => {
+                self.error = Some(format!(
+                    "Could not reload '{}' after it was changed: {}",
+                    location, err
+                ));
+                return;
+            }
+        };
+        let merge = merge::three_way(&self.synced, &self.tasks, &theirs);
+        self.synced = theirs;
+        // Changes made elsewhere were not made by the local author
+        self.apply_as(&location, |tasks| tasks.replace(merge.tasks));
+        if !merge.conflicts.is_empty() {
+            self.error = Some(format!(
+                "{} tasks were changed both here and in '{}', choose which versions to keep",
+                merge.conflicts.len(),
+                location
+            ));
+            self.conflicts = Some(ImportPreview::new(
+                format!("changes made to '{}'", location),
+                merge.conflicts,
+                &self.tasks,
+                Resolution::KeepLocal,
+            ));
+        }
+    }
+
+    /// Writes changed tasks right away to stores that support it
+    fn store_changes(&mut self, changes: &[Change]) {
+        self.unsaved = true;
+        if self.load_failed || self.read_only {
+            return;
+        }
+        let Some(store) = &mut self.store else {
+            return;
+        };
+        // Changes made elsewhere before these are still merged on the next check
+        let seen = store.incremental()
+            && self
+                .watcher
+                .as_ref()
+                .is_some_and(|watcher| watcher.changed().is_ok_and(|changed| !changed));
+        for change in changes {
+            let result = match &change.after {
+                Some(task) => store.upsert(task),
+                None => store.delete(change.id()),
+            };
+            if let Err(err) = result {
+                self.error = Some(format!(
+                    "Could not save the task list to '{}': {}",
+                    store.location(),
+                    err
+                ));
+                return;
+            }
+            if store.incremental() {
+                match &change.after {
+                    Some(task) => self.synced.insert(change.id(), task.clone()),
+                    None => self.synced.remove(&change.id()),
+                };
+            }
+        }
+        if let (true, Some(watcher)) = (seen, &mut self.watcher) {
+            watcher.reset();
+        }
+    }
+
+    /// Applies a change to the task list so that it can be undone
+    pub fn execute<R>(&mut self, label: &str, f: impl FnOnce(&mut TaskCollection) -> R) -> R {
+        self.commit_edit();
+        let (result, command) = Command::execute(label, &mut self.tasks, f);
+        if let Some(command) = command {
+            self.record(command);
+        }
+        self.sync_edit();
+        result
+    }
+
+    /// Starts tracking the time of `id`
+    pub fn start_task(&mut self, id: Uuid) -> error::Result<()> {
+        let clock = FixedClock(self.clock.now());
+        self.execute("Start task", |tasks| tasks.start_with(id, &clock))
+    }
+
+    /// Finishes `id`, a task that was never started is started first
+    pub fn finish_task(&mut self, id: Uuid) -> error::Result<()> {
+        let clock = FixedClock(self.clock.now());
+        self.execute("Finish task", |tasks| {
+            tasks.start_with(id, &clock)?;
+            tasks.finish_with(id, &clock)
+        })
+    }
+
+    /// Applies a change that is not undone, such as time tracked by the pomodoro
+    fn apply<R>(&mut self, f: impl FnOnce(&mut TaskCollection) -> R) -> R {
+        let author = self.author.clone();
+        self.apply_as(&author, f)
+    }
+
+    /// Like [`Workspace::apply`], logging the change under `author`
+    fn apply_as<R>(&mut self, author: &str, f: impl FnOnce(&mut TaskCollection) -> R) -> R {
+        self.commit_edit();
+        let (result, command) = Command::execute("", &mut self.tasks, f);
+        if let Some(command) = command {
+            self.store_changes(&command.changes);
+            self.log_as(&command.changes, author);
+        }
+        self.sync_edit();
+        result
+    }
+
+    /// Adds an applied command to the undo history and the event log
+    fn record(&mut self, command: Command) {
+        self.store_changes(&command.changes);
+        self.log(&command.changes);
+        self.history.record(command);
+    }
+
+    fn log(&mut self, changes: &[Change]) {
+        let author = self.author.clone();
+        self.log_as(changes, &author);
+    }
+
+    fn log_as(&mut self, changes: &[Change], author: &str) {
+        let now = self.clock.now();
+        let events = changes
+            .iter()
+            .map(|change| Event::new(change, now, author))
+            .collect();
+        self.append_events(events);
+    }
+
+    fn append_events(&mut self, events: Vec<Event>) {
+        let Some(log) = self.event_log.as_ref().filter(|_| !self.read_only) else {
+            return;
+        };
+        if let Err(err) = log.append(&events) {
+            self.error = Some(format!(
+                "Could not write to the event log '{}': {}",
+                log.path().display(),
+                err
+            ));
+        }
+        self.edit_events.extend(
+            events
+                .into_iter()
+                .filter(|event| Some(event.id) == self.edit),
+        );
+    }
+
+    /// The task being edited
+    pub fn edited(&self) -> Option<Uuid> {
+        self.edit
+    }
+
+    /// The edited task, changed in place, the changes are recorded by [`Workspace::commit_edit`]
+    pub fn edited_mut(&mut self) -> Option<&mut Task> {
+        self.edit.and_then(|id| self.tasks.get_mut(id))
+    }
+
+    /// Logged events of the edited task
+    pub fn edit_events(&self) -> &[Event] {
+        &self.edit_events
+    }
+
+    /// Records the edits to the edited task made since the last recorded change
+    pub fn commit_edit(&mut self) {
+        let (Some(id), Some(before)) = (self.edit, self.edit_snapshot.take()) else {
+            return;
+        };
+        let after = self.tasks.get(&id).cloned();
+        if after.as_ref() != Some(&before) {
+            let mut changes = vec![Change {
+                before: Some(before),
+                after: after.clone(),
+            }];
+            // Parents keep the name of their subtasks
+            let (_, renamed) = Command::execute("", &mut self.tasks, |tasks| tasks.sync_name(id));
+            changes.extend(renamed.into_iter().flat_map(|command| command.changes));
+            self.record(Command {
+                label: "Edit task".to_string(),
+                changes,
+            });
+        }
+        self.edit_snapshot = after;
+    }
+
+    /// Follows the edited task through undo and redo, ending the edit if it is gone
+    fn sync_edit(&mut self) {
+        self.edit_snapshot = self.edit.and_then(|id| self.tasks.get(&id).cloned());
+        if self.edit_snapshot.is_none() {
+            self.edit = None;
+        }
+    }
+
+    /// Starts editing the task `id`, `None` ends the edit
+    pub fn open_edit(&mut self, id: Option<Uuid>) {
+        self.commit_edit();
+        self.edit = id;
+        self.sync_edit();
+        self.edit_events = match (self.edit, &self.event_log) {
+            (Some(id), Some(log)) => log.task_history(id).unwrap_or_else(|err| {
+                self.error = Some(format!("Could not read the event log: {}", err));
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+    }
+
+    pub fn undo(&mut self) {
+        self.commit_edit();
+        // Undoing is logged as the reverse change
+        let reverted: Option<Vec<Change>> = self.history.undo(&mut self.tasks).map(|command| {
+            command
+                .changes
+                .iter()
+                .map(|change| Change {
+                    before: change.after.clone(),
+                    after: change.before.clone(),
+                })
+                .collect()
+        });
+        if let Some(changes) = reverted {
+            self.store_changes(&changes);
+            self.log(&changes);
+        }
+        self.sync_edit();
+    }
+
+    pub fn redo(&mut self) {
+        self.commit_edit();
+        let changes = self
+            .history
+            .redo(&mut self.tasks)
+            .map(|command| command.changes.clone());
+        if let Some(changes) = changes {
+            self.store_changes(&changes);
+            self.log(&changes);
+        }
+        self.sync_edit();
+    }
+
+    /// Replaces the task list with the one resulting from replaying the event log
+    pub fn rebuild_from_log(&mut self) {
+        let Some(log) = &self.event_log else {
+            self.error = Some("This task store keeps no event log".to_string());
+            return;
+        };
+        match log.read() {
+            Ok(logged) => {
+                let rebuilt = events::rebuild(&logged);
+                self.execute("Rebuild from log", |tasks| {
+                    tasks.replace(rebuilt.into_values())
+                });
+            }
+            Err(err) => self.error = Some(format!("Could not read the event log: {}", err)),
+        }
+    }
+
+    /// Replaces the task list with a backup, the current one is backed up first
+    pub fn restore(&mut self, backup: &storage::Backup) {
+        let input = match backup.read() {
+            Ok(input) => input,
+            Err(err) => {
+                self.error = Some(format!("Could not read backup: {}", err));
+                return;
+            }
+        };
+        if let Some(store) = &mut self.store {
+            if let Err(err) = store.backup() {
+                self.error = Some(format!("Could not back up the current task list: {}", err));
+                return;
+            }
+        }
+        if let Some(doc) = self.load_document(&input) {
+            // The restored list replaces one that could not be read
+            self.load_failed = false;
+            self.open_edit(None);
+            self.execute("Restore backup", |tasks| tasks.replace(doc.tasks));
+            self.save();
+        }
+    }
+
+    /// Starts a pomodoro for `task`, or stops the running one
+    ///
+    /// A task with a preset of its own starts with the settings of that preset.
+    pub fn toggle_pomodoro(&mut self, task: Option<Uuid>) {
+        match self.pomodoro.take() {
+            Some(pomo) => {
+                // Done sessions were recorded when they were done
+                if pomo.status_with(self.clock.as_ref()) != PomodoroStatus::Done {
+                    self.record_session(pomo.session_with(self.clock.as_ref()));
+                }
+                self.track_pomodoro_task(pomo.task, false, self.clock.now());
+            }
+            None => {
+                let preset = task
+                    .and_then(|id| self.tasks.get(&id))
+                    .and_then(|task| task.pomodoro_preset.as_deref())
+                    .and_then(|name| Preset::find(&self.pomodoro_presets, name));
+                if let Some(settings) = preset {
+                    self.pomodoro_settings = settings;
+                }
+                let mut pomo =
+                    Pomodoro::new_with(self.pomodoro_settings.clone(), self.clock.as_ref());
+                pomo.task = task;
+                self.pomodoro = Some(pomo);
+            }
+        }
+    }
+
+    /// Pauses the running phase of the pomodoro, or resumes it if it is paused
+    pub fn toggle_pause(&mut self) {
+        let Some(pomo) = &mut self.pomodoro else {
+            return;
+        };
+        let clock = self.clock.as_ref();
+        let PomodoroStatus::Running(phase, _) = pomo.status_with(clock) else {
+            return;
+        };
+        let paused = pomo.is_paused();
+        if paused {
+            pomo.resume_with(clock);
+        } else {
+            pomo.pause_with(clock);
+        }
+        let task = pomo.task;
+        let now = clock.now();
+        self.track_pomodoro_task(task, paused && phase == Phase::Work, now);
+    }
+
+    /// Ends the running phase of the pomodoro early
+    pub fn skip_phase(&mut self) {
+        if let Some(pomo) = &mut self.pomodoro {
+            pomo.skip_with(self.clock.as_ref());
+        }
+    }
+
+    /// Starts the phase the pomodoro waits for
+    pub fn start_next_phase(&mut self) {
+        if let Some(pomo) = &mut self.pomodoro {
+            pomo.start_next_with(self.clock.as_ref());
+        }
+    }
+
+    /// Catches up with the phases of the pomodoro that ended, announcing them through `notifier`
+    pub fn poll_pomodoro(&mut self, notifier: &mut dyn Notifier) {
+        let Some(pomo) = &mut self.pomodoro else {
+            return;
+        };
+        for event in pomo.poll_with(self.clock.as_ref()) {
+            self.pomodoro_event(event, notifier);
+        }
+    }
+
+    /// The task worked on: the one of the pomodoro, else a running or the `linked` one
+    pub fn active_task(&self, linked: Option<Uuid>) -> Option<Uuid> {
+        self.pomodoro
+            .as_ref()
+            .and_then(|pomo| pomo.task)
+            .or_else(|| {
+                self.tasks
+                    .sorted()
+                    .into_iter()
+                    .find(|task| task.is_running())
+                    .map(Task::get_uuid)
+            })
+            .or(linked)
+    }
+
+    /// Notifies about a phase change of the pomodoro and tracks the time of its task
+    fn pomodoro_event(&mut self, event: PomodoroEvent, notifier: &mut dyn Notifier) {
+        let Some(pomo) = &self.pomodoro else {
+            return;
+        };
+        let task = pomo.task;
+        let task_name = task
+            .and_then(|id| self.tasks.get(&id))
+            .map(|task| task.name.as_str());
+        let message = self.notify_settings.message(event, pomo, task_name);
+        match event {
+            PomodoroEvent::Completed(Phase::Work, _) => {
+                if let (Some(id), false) = (task, self.read_only) {
+                    let result = self.apply(|tasks| tasks.add_pomodoro(id));
+                    self.report(result);
+                }
+            }
+            PomodoroEvent::Done => self.record_session(pomo.session_with(self.clock.as_ref())),
+            _ => (),
+        }
+        if let Some(message) = message {
+            let sent = notifier.notify(&message);
+            self.report(sent);
+        }
+        // Caught up phases are tracked at their boundaries, not at the time of the poll
+        match event {
+            PomodoroEvent::Completed(_, end) => self.track_pomodoro_task(task, false, end),
+            PomodoroEvent::Started(phase, start) => {
+                self.track_pomodoro_task(task, phase == Phase::Work, start)
+            }
+            PomodoroEvent::Waiting(_) | PomodoroEvent::Done => {
+                self.track_pomodoro_task(task, false, self.clock.now())
+            }
+        }
+    }
+
+    fn record_session(&mut self, session: Session) {
+        self.sessions.push(session);
+        self.save();
+    }
+
+    /// Resumes or pauses the time interval of the task a pomodoro is linked to, as of `at`
+    fn track_pomodoro_task(&mut self, task: Option<Uuid>, running: bool, at: DateTime<Utc>) {
+        let Some(id) = task else {
+            return;
+        };
+        let clock = FixedClock(at);
+        // Only actual changes are logged, so pausing a paused task leaves no event
+        let result = match (running, self.tasks.get(&id)) {
+            (_, None) => return,
+            _ if self.read_only => return,
+            (true, Some(task)) if !task.is_running() => {
+                self.apply(|tasks| tasks.resume_with(id, &clock))
+            }
+            (false, Some(task)) if task.is_running() => {
+                self.apply(|tasks| tasks.pause_with(id, &clock))
+            }
+            _ => return,
+        };
+        self.report(result);
+    }
+
+    fn report(&mut self, result: error::Result<()>) {
+        if let Err(err) = result {
+            self.error = Some(err.to_string());
+        }
+    }
+}
+
+fn save_document(
+    store: &mut dyn TaskStore,
+    doc: &Document,
+    max_backups: usize,
+) -> Result<(), String> {
+    if let Some(backups) = store.backups() {
+        backups.max = max_backups;
+    }
+    store.save(doc).map_err(|err| {
+        format!(
+            "Could not save the task list to '{}': {}",
+            store.location(),
+            err
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    use chrono::{Duration, TimeZone};
+
+    use crate::clock::ManualClock;
+    use crate::notify::NoNotifier;
+    use crate::storage::memory::MemoryStore;
+    use crate::storage::{Backup, TempDir};
+
+    /// A workspace on an empty store, taking its time from a clock standing at 09:00
+    fn workspace() -> (Workspace, Rc<ManualClock>) {
+        let clock = Rc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2023, 5, 1, 9, 0, 0).unwrap(),
+        ));
+        let mut ws = Workspace::with_store(Box::new(MemoryStore::new(None)));
+        ws.set_clock(Box::new(clock.clone()));
+        (ws, clock)
+    }
+
+    #[test]
+    fn undo_edit() {
+        let (mut ws, _) = workspace();
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task.clone());
+        });
+
+        ws.open_edit(Some(id));
+        ws.edited_mut().unwrap().name = "Edited".to_string();
+        ws.commit_edit();
+        assert_eq!(ws.history().undo_label(), Some("Edit task"));
+
+        ws.undo();
+        assert_eq!(ws.tasks()[&id], task);
+        ws.undo();
+        // The edit ends with the task it was of
+        assert!(ws.tasks().is_empty());
+        assert_eq!(ws.edited(), None);
+    }
+
+    #[test]
+    fn restore_over_failed_load() {
+        let dir = TempDir::new();
+        let path = dir.join("tasks.json");
+        std::fs::write(&path, "not a task list").unwrap();
+        let mut ws = Workspace::open(&path);
+        ws.load(|| None);
+        assert!(ws.take_error().is_some());
+        assert_eq!(ws.document(), None);
+
+        let task = Task::default();
+        let backup = Backup {
+            path: dir.join("backup.json"),
+            time: Utc::now(),
+        };
+        std::fs::write(&backup.path, Document::new(vec![task.clone()]).to_json()).unwrap();
+        ws.restore(&backup);
+        assert_eq!(ws.take_error(), None);
+        let saved = Document::load(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.tasks, vec![task]);
+    }
+
+    #[test]
+    fn broken_links_dropped_on_load() {
+        let mut parent = Task::default();
+        let mut child = Task::default();
+        parent
+            .add_subtask(child.get_uuid(), child.name.clone())
+            .unwrap();
+        child
+            .add_subtask(parent.get_uuid(), parent.name.clone())
+            .unwrap();
+        child
+            .add_subtask(Uuid::new_v4(), "Missing".to_string())
+            .unwrap();
+        let (parent_id, child_id) = (parent.get_uuid(), child.get_uuid());
+
+        let doc = Document::new(vec![parent, child]);
+        let mut ws = Workspace::with_store(Box::new(MemoryStore::new(Some(doc))));
+        ws.load(|| None);
+        assert!(ws.take_error().is_some());
+        // One of the two links forming the cycle is kept, the link to the missing task is gone
+        let links = |id| ws.tasks()[&id].get_subtasks().map_or(0, Vec::len);
+        assert_eq!(links(parent_id) + links(child_id), 1);
+        assert!(!ws.tasks().descendants(parent_id).contains(&parent_id));
+        assert!(ws.unsaved_document().is_some());
+    }
+
+    #[test]
+    fn execute_and_save() {
+        let task = Task::default();
+        let id = task.get_uuid();
+        let mut doc = Document::new(vec![task.clone()]);
+        let settings = PomodoroSettings {
+            cycles: 2,
+            ..Default::default()
+        };
+        doc.pomodoro_settings = Some(settings.clone());
+        let mut ws = Workspace::with_store(Box::new(MemoryStore::new(Some(doc))));
+        ws.persist_history = true;
+        ws.load(|| None);
+        assert_eq!(ws.tasks()[&id], task);
+        assert_eq!(ws.pomodoro_settings, settings);
+
+        ws.start_task(id).unwrap();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(Task::default());
+        });
+        ws.undo();
+        ws.save();
+        assert_eq!(ws.take_error(), None);
+
+        let saved = ws.into_store().unwrap().load().unwrap().unwrap();
+        assert_eq!(saved.tasks.len(), 1);
+        assert!(saved.tasks[0].is_started());
+        assert_eq!(saved.history.unwrap().undo_label(), Some("Start task"));
+        assert_eq!(saved.pomodoro_settings, Some(settings));
+    }
+
+    #[test]
+    fn changes_stored_right_away() {
+        let (mut ws, clock) = workspace();
+        ws.load(|| None);
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task.clone());
+        });
+        ws.start_task(id).unwrap();
+
+        let mut store = ws.into_store().unwrap();
+        let stored = store.load().unwrap().unwrap();
+        assert_eq!(stored.tasks.len(), 1);
+        assert_eq!(stored.tasks[0].get_started(), Some(clock.now()));
+    }
+
+    #[test]
+    fn finish_unstarted_task() {
+        let (mut ws, clock) = workspace();
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task);
+        });
+
+        clock.advance(Duration::minutes(10));
+        ws.finish_task(id).unwrap();
+        let task = &ws.tasks()[&id];
+        assert_eq!(task.get_started(), Some(clock.now()));
+        assert_eq!(task.get_finished(), Some(clock.now()));
+        assert_eq!(ws.history().undo_label(), Some("Finish task"));
+    }
+
+    #[test]
+    fn reload_without_undo() {
+        let (mut ws, _) = workspace();
+        ws.load(|| None);
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task.clone());
+        });
+
+        let mut changed = task.clone();
+        changed.name = "Changed elsewhere".to_string();
+        ws.store.as_mut().unwrap().upsert(&changed).unwrap();
+        ws.reload();
+        assert_eq!(ws.tasks()[&id], changed);
+        // Undoing goes back past the merged change to the local one
+        assert_eq!(ws.history().undo_label(), Some("Create task"));
+        assert_eq!(ws.history().redo_label(), None);
+    }
+
+    #[test]
+    fn tracking_without_undo() {
+        let (mut ws, clock) = workspace();
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task);
+        });
+
+        ws.toggle_pomodoro(Some(id));
+        ws.poll_pomodoro(&mut NoNotifier);
+        assert!(ws.tasks()[&id].is_running());
+        clock.advance(Duration::minutes(10));
+        ws.toggle_pause();
+        let task = &ws.tasks()[&id];
+        assert!(!task.is_running());
+        assert_eq!(task.tracked_time(), Duration::minutes(10));
+        // The pomodoro would fill the undo history otherwise
+        assert_eq!(ws.history().undo_label(), Some("Create task"));
+    }
+
+    #[test]
+    fn caught_up_phases_tracked_at_boundaries() {
+        let (mut ws, clock) = workspace();
+        let task = Task::default();
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task);
+        });
+
+        // Polled again in the short break: the work phase ended while nobody polled
+        let start = clock.now();
+        ws.toggle_pomodoro(Some(id));
+        clock.advance(Duration::minutes(28));
+        ws.poll_pomodoro(&mut NoNotifier);
+
+        let task = &ws.tasks()[&id];
+        assert!(!task.is_running());
+        assert_eq!(task.intervals.len(), 1);
+        assert_eq!(task.intervals[0].start, start);
+        assert_eq!(task.intervals[0].end, Some(start + Duration::minutes(25)));
+    }
+
+    #[test]
+    fn task_preset_applied_on_start() {
+        let (mut ws, _) = workspace();
+        let task = Task {
+            pomodoro_preset: Some("90/20".to_string()),
+            ..Task::default()
+        };
+        let id = task.get_uuid();
+        ws.execute("Create task", |tasks| {
+            tasks.insert(task);
+        });
+
+        ws.toggle_pomodoro(Some(id));
+        let work_time = ws.pomodoro().map(|pomo| pomo.settings.work_time);
+        assert_eq!(work_time, Some(Duration::minutes(90)));
+        assert_eq!(ws.pomodoro_settings.work_time, Duration::minutes(90));
+    }
+}